@@ -0,0 +1,450 @@
+use crate::cli::{Cli, Commands, ZoxideCommands};
+use crate::config::{self, Config, ThemeConfig, TmuxConfig};
+use crate::connect::{self, ConnectOptions};
+use crate::list::{self, ListOptions};
+use crate::shell::{RealShell, Shell};
+use crate::theme::{self, ColorMode, Paint, Theme};
+use crate::tmux::{Socket, WithSocket};
+use crate::transcript::{RecordingShell, ReplayShell};
+use crate::{error, filter, fzf, gc, history, pins, snapshot, status, template, zoxide};
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Parse the process arguments and run the command, reporting errors on
+/// stderr.
+pub(crate) fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run_with_shell(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error::report(&e);
+            ExitCode::from(error::exit_code(&e))
+        }
+    }
+}
+
+/// `NITRO_REPLAY` answers every command from a transcript; `NITRO_RECORD`
+/// runs them for real and writes a transcript.
+fn run_with_shell(cli: Cli) -> Result<()> {
+    let var = |k: &str| RealShell.env_var(k).filter(|v| !v.is_empty());
+    if let Some(path) = var("NITRO_REPLAY") {
+        return run(cli, &ReplayShell::load(Path::new(&path))?, true);
+    }
+    if let Some(path) = var("NITRO_RECORD") {
+        let sh = RecordingShell::create(RealShell, Path::new(&path))?;
+        return run(cli, &sh, false);
+    }
+    run(cli, &RealShell, false)
+}
+
+/// Fail commands whose whole point is writing nitro's own files, which a
+/// replay must leave alone.
+fn no_replay(replay: bool, command: &str) -> Result<()> {
+    if replay {
+        anyhow::bail!("nitro {} cannot run from a transcript", command);
+    }
+    Ok(())
+}
+
+/// Run `cli` on `real`. With `replay`, `real` answers from a transcript:
+/// nothing that the transcript does not hold (directory checks, nitro's
+/// own files) may change the outcome, and nothing is written.
+fn run<S: Shell>(cli: Cli, real: &S, replay: bool) -> Result<()> {
+    let cfg = config::load(real)?;
+    let socket = socket_from_sources(
+        cli.socket_name.as_deref(),
+        cli.socket_path.as_deref(),
+        real.env_var("NITRO_TMUX_SOCKET").as_deref(),
+        &cfg.tmux,
+    );
+    let sh = WithSocket::new(real, &socket);
+    let color = color_mode(cli.color.as_deref(), cli.no_color, &cfg.theme)?;
+    error::set_color(theme::color_enabled(
+        real,
+        color,
+        std::io::stderr().is_terminal(),
+    ));
+
+    match cli.command {
+        Commands::List {
+            pins,
+            tmux,
+            zoxide,
+            icons,
+            strict,
+            all_servers,
+            exclude,
+            keep_missing,
+            min_score,
+            scores,
+            json,
+            delimited,
+            long,
+            template,
+            types,
+        } => {
+            let mut opts = list_options_from_flags(pins, tmux, zoxide, icons);
+            opts.strict = strict;
+            opts.all_servers = all_servers;
+            opts.filters = filter::Filters::new(&sh, &cfg.filter, &exclude)?;
+            // The recording machine's directories are not here to check
+            opts.prune_missing = !keep_missing && !replay;
+            if !replay {
+                opts.dir_cache = config::cache_dir(real).map(|d| d.join("dirs.json"));
+            }
+            opts.min_score = min_score;
+            opts.show_scores = scores;
+            opts.json = json;
+            opts.delimited = delimited;
+            opts.long = long;
+            opts.width = terminal_width(real);
+            opts.templates = template::Templates::new(&cfg.template, &template)?;
+            opts.types = types;
+            let is_tty = std::io::stdout().is_terminal();
+            opts.paint = Paint::detect(real, Theme::from_config(&cfg.theme)?, color, is_tty);
+            if all_servers {
+                // Every server is addressed by its own socket name
+                list::run_list(real, opts)
+            } else {
+                list::run_list(&sh, opts)
+            }
+        }
+        Commands::Last => {
+            history::run_back(&sh, connect_options(&cfg, vec![], None, false, replay), 1)
+        }
+        Commands::Back { n } => {
+            history::run_back(&sh, connect_options(&cfg, vec![], None, false, replay), n)
+        }
+        Commands::Pin { target } => {
+            no_replay(replay, "pin")?;
+            pins::run_pin(real, &target)
+        }
+        Commands::Unpin { target } => {
+            no_replay(replay, "unpin")?;
+            pins::run_unpin(real, &target)
+        }
+        Commands::Pins => pins::run_pins(real),
+        Commands::Save { commands, file } => {
+            no_replay(replay, "save")?;
+            snapshot::run_save(&sh, file.as_deref(), commands)
+        }
+        Commands::Restore { file } => snapshot::run_restore(&sh, file.as_deref()),
+        Commands::Gc {
+            idle,
+            exclude,
+            max,
+            dry_run,
+        } => {
+            let opts = gc::GcOptions::new(&sh, &cfg.gc, idle.as_deref(), &exclude, max, dry_run)?;
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            gc::run_gc(&sh, &opts, now)
+        }
+        Commands::Fzf {
+            pins,
+            tmux,
+            zoxide,
+            icons,
+            list,
+            preview,
+            kill,
+            rename,
+        } => {
+            if let Some(key) = preview {
+                return fzf::run_preview(&sh, &key);
+            }
+            if let Some(key) = kill {
+                return fzf::run_kill(&sh, &key);
+            }
+            if let Some([key, name]) = rename.as_deref() {
+                return fzf::run_rename(&sh, key, name);
+            }
+            let mut opts = list_options_from_flags(pins, tmux, zoxide, icons);
+            opts.filters = filter::Filters::new(&sh, &cfg.filter, &[])?;
+            opts.delimited = true;
+            opts.templates = template::Templates::new(&cfg.template, &[])?;
+            let is_tty = std::io::stdout().is_terminal();
+            opts.paint = Paint::detect(real, Theme::from_config(&cfg.theme)?, color, is_tty);
+            if list {
+                fzf::apply_prompt(real, &mut opts);
+                return list::run_list(&sh, opts);
+            }
+            // Reloads print to fzf, so they are told whether to color
+            let colored = theme::color_enabled(real, color, is_tty);
+            let mut fzf_opts = fzf::FzfOptions::new(
+                fzf_callback(&socket, colored, pins, tmux, zoxide, icons)?,
+                colored,
+            );
+            fzf_opts.fzf_args = cfg.fzf.options.clone();
+            let connect = connect_options(&cfg, vec![], None, false, replay);
+            fzf::run_fzf(&sh, &opts, &fzf_opts, connect)
+        }
+        Commands::Status {
+            session,
+            plain,
+            cache,
+        } => {
+            let mut opts =
+                status::StatusOptions::new(&cfg.status, session, plain, cache.as_deref())?;
+            if replay {
+                opts.cache = 0;
+            } else {
+                opts.server = socket.path(real)?.display().to_string();
+            }
+            status::run_status(&sh, &opts)
+        }
+        Commands::Zoxide {
+            command: ZoxideCommands::Prune { dry_run },
+        } => {
+            no_replay(replay, "zoxide prune")?;
+            zoxide::run_prune(real, dry_run)
+        }
+        Commands::Connect {
+            dir,
+            no_fail,
+            mkdir,
+            multi,
+            attach,
+            key,
+            name_tokens,
+        } => {
+            let (name_tokens, multi) = if name_tokens.is_empty() && !std::io::stdin().is_terminal()
+            {
+                // `fzf --multi | nitro connect`: one session per line
+                (
+                    std::io::read_to_string(std::io::stdin())?
+                        .lines()
+                        .map(str::to_string)
+                        .collect(),
+                    true,
+                )
+            } else {
+                (name_tokens, multi)
+            };
+            let mut opts = connect_options(&cfg, name_tokens, dir, mkdir, replay);
+            opts.multi = multi;
+            opts.attach = attach;
+            opts.key = key;
+            match connect::run_connect(&sh, opts) {
+                Ok(()) => Ok(()),
+                Err(e) if no_fail => {
+                    error::report(&e);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+    }
+}
+
+fn connect_options(
+    cfg: &Config,
+    tokens: Vec<String>,
+    dir: Option<PathBuf>,
+    mkdir: bool,
+    replay: bool,
+) -> ConnectOptions {
+    let mut opts = ConnectOptions::new(tokens, cfg);
+    opts.dir = dir;
+    opts.mkdir = mkdir;
+    opts.history = !replay;
+    opts.replay = replay;
+    opts
+}
+
+/// Pick the tmux server: CLI flags, then `NITRO_TMUX_SOCKET`, then config.
+fn socket_from_sources(
+    cli_name: Option<&str>,
+    cli_path: Option<&Path>,
+    env: Option<&str>,
+    cfg: &TmuxConfig,
+) -> Socket {
+    if let Some(p) = cli_path {
+        return Socket::Path(p.to_path_buf());
+    }
+    if let Some(n) = cli_name {
+        return Socket::Name(n.to_string());
+    }
+    if let Some(v) = env.filter(|v| !v.is_empty()) {
+        return Socket::parse(v);
+    }
+    if let Some(p) = &cfg.socket_path {
+        return Socket::Path(p.clone());
+    }
+    match &cfg.socket_name {
+        Some(n) => Socket::Name(n.clone()),
+        None => Socket::Default,
+    }
+}
+
+/// `--no-color`, then `--color`, then the config's `theme.color`.
+fn color_mode(flag: Option<&str>, no_color: bool, cfg: &ThemeConfig) -> Result<ColorMode> {
+    if no_color {
+        return Ok(ColorMode::Never);
+    }
+    match flag.or(cfg.color.as_deref()) {
+        Some(mode) => mode.parse(),
+        None => Ok(ColorMode::Auto),
+    }
+}
+
+/// Width to fit `--long` lines to: `$COLUMNS`, else the terminal's when
+/// stdout is one. Piped output is not truncated.
+fn terminal_width<S: Shell>(sh: &S) -> Option<usize> {
+    if let Some(cols) = sh.env_var("COLUMNS").and_then(|c| c.parse().ok()) {
+        return Some(cols);
+    }
+    let stdout = std::io::stdout();
+    if !stdout.is_terminal() {
+        return None;
+    }
+    terminal_size::terminal_size_of(&stdout).map(|(w, _)| w.0 as usize)
+}
+
+/// How the fzf bindings run this nitro again: same binary, server, colors
+/// and list flags.
+fn fzf_callback(
+    socket: &Socket,
+    colored: bool,
+    pins: bool,
+    tmux: bool,
+    zoxide: Option<Option<usize>>,
+    icons: bool,
+) -> Result<Vec<String>> {
+    let exe = std::env::current_exe().context("cannot find the nitro binary")?;
+    let mut words = vec![exe.display().to_string()];
+    words.extend(socket.args());
+    words.push("--color".into());
+    words.push(if colored { "always" } else { "never" }.into());
+    words.push("fzf".into());
+    let flags = [(pins, "-p"), (tmux, "-t"), (icons, "--icons")];
+    words.extend(
+        flags
+            .iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, f)| f.to_string()),
+    );
+    match zoxide {
+        Some(Some(n)) => words.push(format!("--zoxide={}", n)),
+        Some(None) => words.push("-z".into()),
+        None => {}
+    }
+    Ok(words)
+}
+
+fn list_options_from_flags(
+    pins: bool,
+    tmux: bool,
+    zoxide: Option<Option<usize>>,
+    icons: bool,
+) -> ListOptions {
+    // No source flag means every source; otherwise only the named ones
+    let any = pins || tmux || zoxide.is_some();
+    let (include_zox, z_limit) = match zoxide {
+        None => (!any, None),
+        Some(inner) => (true, inner),
+    };
+    ListOptions {
+        include_pins: pins || !any,
+        include_tmux: tmux || !any,
+        include_zox,
+        z_limit,
+        icons,
+        ..ListOptions::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_flag_translation_defaults() {
+        // No flags => include_tmux true, include_zox true, no limit
+        let lo = list_options_from_flags(false, false, None, false);
+        assert!(lo.include_pins);
+        assert!(lo.include_tmux);
+        assert!(lo.include_zox);
+        assert_eq!(lo.z_limit, None);
+        assert!(!lo.icons);
+    }
+
+    #[test]
+    fn list_flag_translation_tmux_only() {
+        let lo = list_options_from_flags(false, true, None, false);
+        assert!(!lo.include_pins);
+        assert!(lo.include_tmux);
+        assert!(!lo.include_zox);
+    }
+
+    #[test]
+    fn list_flag_translation_zoxide_only_unlimited() {
+        let lo = list_options_from_flags(false, false, Some(None), false);
+        assert!(!lo.include_tmux);
+        assert!(lo.include_zox);
+        assert_eq!(lo.z_limit, None);
+    }
+
+    #[test]
+    fn list_flag_translation_zoxide_with_limit() {
+        let lo = list_options_from_flags(false, false, Some(Some(5)), true);
+        assert!(!lo.include_tmux);
+        assert!(lo.include_zox);
+        assert_eq!(lo.z_limit, Some(5));
+        assert!(lo.icons);
+    }
+
+    #[test]
+    fn list_flag_translation_pins_and_tmux() {
+        let lo = list_options_from_flags(true, true, None, false);
+        assert!(lo.include_pins);
+        assert!(lo.include_tmux);
+        assert!(!lo.include_zox);
+    }
+
+    #[test]
+    fn color_mode_precedence() -> Result<()> {
+        let mut cfg = ThemeConfig::default();
+        assert_eq!(color_mode(None, false, &cfg)?, ColorMode::Auto);
+        cfg.color = Some("always".into());
+        assert_eq!(color_mode(None, false, &cfg)?, ColorMode::Always);
+        assert_eq!(color_mode(Some("never"), false, &cfg)?, ColorMode::Never);
+        assert_eq!(color_mode(None, true, &cfg)?, ColorMode::Never);
+        cfg.color = Some("sometimes".into());
+        assert!(color_mode(None, false, &cfg).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn socket_precedence_cli_env_config() {
+        let cfg = TmuxConfig {
+            socket_name: Some("cfg".into()),
+            ..TmuxConfig::default()
+        };
+        assert_eq!(
+            socket_from_sources(Some("cli"), None, Some("env"), &cfg),
+            Socket::Name("cli".into())
+        );
+        assert_eq!(
+            socket_from_sources(None, Some(Path::new("/p")), Some("env"), &cfg),
+            Socket::Path("/p".into())
+        );
+        assert_eq!(
+            socket_from_sources(None, None, Some("/run/env.sock"), &cfg),
+            Socket::Path("/run/env.sock".into())
+        );
+        assert_eq!(
+            socket_from_sources(None, None, None, &cfg),
+            Socket::Name("cfg".into())
+        );
+        assert_eq!(
+            socket_from_sources(None, None, Some(""), &TmuxConfig::default()),
+            Socket::Default
+        );
+    }
+}
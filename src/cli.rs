@@ -1,17 +1,61 @@
+use crate::project::ProjectType;
 use clap::{ArgAction, Parser, Subcommand, ValueHint};
 use std::path::PathBuf;
 
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  1  unexpected error
+  2  invalid usage
+  3  tmux or zoxide not found on PATH
+  4  no tmux server running
+  5  empty session name
+  6  cannot attach: not a terminal
+  7  tmux or zoxide command failed
+  8  session directory does not exist";
+
 #[derive(Debug, Parser)]
-#[command(name = "nitro", version, about = "Fast tmux sessions via zoxide")]
+#[command(
+    name = "nitro",
+    version,
+    about = "Fast tmux sessions via zoxide",
+    after_help = EXIT_CODES_HELP
+)]
 pub struct Cli {
+    /// tmux server socket name (as tmux -L); overrides NITRO_TMUX_SOCKET and config
+    #[arg(short = 'L', long = "socket-name", global = true)]
+    pub socket_name: Option<String>,
+    /// tmux server socket path (as tmux -S); overrides NITRO_TMUX_SOCKET and config
+    #[arg(
+        short = 'S',
+        long = "socket-path",
+        global = true,
+        conflicts_with = "socket_name",
+        value_hint = ValueHint::FilePath
+    )]
+    pub socket_path: Option<PathBuf>,
+    /// Color output: auto (terminals only, honors NO_COLOR and CLICOLOR_FORCE), always or never
+    #[arg(
+        long = "color",
+        value_name = "WHEN",
+        global = true,
+        value_parser = ["auto", "always", "never"]
+    )]
+    pub color: Option<String>,
+    /// Same as --color never
+    #[arg(long = "no-color", global = true, conflicts_with = "color")]
+    pub no_color: bool,
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
-    /// List tmux sessions and/or zoxide results
+    /// List pinned entries, tmux sessions and/or zoxide results
     List {
+        /// Include pinned entries
+        #[arg(short = 'p', long = "pins", action = ArgAction::SetTrue)]
+        pins: bool,
         /// Include tmux sessions
         #[arg(short = 't', long = "tmux", action = ArgAction::SetTrue)]
         tmux: bool,
@@ -19,12 +63,44 @@ pub enum Commands {
         /// Presence without value means all results
         #[arg(short = 'z', long = "zoxide", num_args = 0..=1, value_parser = clap::value_parser!(usize))]
         zoxide: Option<Option<usize>>,
-        /// Use fancy icons (  for tmux,   for zoxide) instead of ASCII [t]/[z]
+        /// Use fancy icons (  for tmux,   for zoxide,
+        /// or a project type's glyph) instead of ASCII [t]/[z]
         #[arg(long = "icons", action = ArgAction::SetTrue)]
         icons: bool,
-        /// Disable colored output (overrides NO_COLOR)
-        #[arg(long = "no-color", action = ArgAction::SetTrue)]
-        no_color: bool,
+        /// Fail if tmux or zoxide errors instead of warning and listing the rest
+        #[arg(long = "strict", action = ArgAction::SetTrue)]
+        strict: bool,
+        /// List sessions from every tmux server socket, labelled "server:name"
+        #[arg(long = "all-servers", action = ArgAction::SetTrue)]
+        all_servers: bool,
+        /// Hide entries matching PATTERN (glob, or regex with "re:"); repeatable
+        #[arg(long = "exclude", value_name = "PATTERN")]
+        exclude: Vec<String>,
+        /// Keep zoxide entries whose directory no longer exists
+        #[arg(long = "keep-missing", action = ArgAction::SetTrue)]
+        keep_missing: bool,
+        /// Drop zoxide entries with a score below N
+        #[arg(long = "min-score", value_name = "N")]
+        min_score: Option<f64>,
+        /// Show zoxide scores after each path
+        #[arg(long = "scores", action = ArgAction::SetTrue)]
+        scores: bool,
+        /// Print entries as a JSON array
+        #[arg(long = "json", action = ArgAction::SetTrue)]
+        json: bool,
+        /// Prefix each line with an exact key and a tab (for `connect --key`)
+        #[arg(long = "delimited", action = ArgAction::SetTrue)]
+        delimited: bool,
+        /// Aligned columns: active/attached marker, windows, clients, last use, path
+        #[arg(short = 'l', long = "long", action = ArgAction::SetTrue)]
+        long: bool,
+        /// Line template, e.g. '{icon} {name:<20} {path}'; prefix with
+        /// "tmux=", "zoxide=" or "pin=" for one source; repeatable
+        #[arg(long = "template", value_name = "TEMPLATE", conflicts_with = "long")]
+        template: Vec<String>,
+        /// Only directories of project type TYPE; repeatable
+        #[arg(long = "type", value_name = "TYPE", value_enum)]
+        types: Vec<ProjectType>,
     },
 
     /// Connect to a tmux session, creating it if missing
@@ -32,16 +108,151 @@ pub enum Commands {
         /// Optional working directory override for new sessions
         #[arg(long = "dir", value_hint = ValueHint::DirPath)]
         dir: Option<PathBuf>,
-        /// Disable colored output (overrides NO_COLOR)
-        #[arg(long = "no-color", action = ArgAction::SetTrue)]
-        no_color: bool,
         /// Do not fail: exit 0 even if an error occurs
         #[arg(long = "no-fail", action = ArgAction::SetTrue)]
         no_fail: bool,
-        /// Full line tokens (supports fzf piping without quotes)
-        #[arg(name = "name", trailing_var_arg = true, num_args = 1..)]
+        /// Create the session directory if it does not exist (without asking)
+        #[arg(long = "mkdir", action = ArgAction::SetTrue)]
+        mkdir: bool,
+        /// Every argument is a separate line (e.g. from `fzf --multi | xargs`)
+        #[arg(short = 'm', long = "multi", action = ArgAction::SetTrue)]
+        multi: bool,
+        /// With several lines, attach to this session instead of the first
+        #[arg(long = "attach", value_name = "NAME")]
+        attach: Option<String>,
+        /// Lines come from `nitro list --delimited`; resolve them by their key
+        #[arg(long = "key", action = ArgAction::SetTrue)]
+        key: bool,
+        /// Full line tokens (supports fzf piping without quotes); read from
+        /// stdin, one line per session, when omitted
+        #[arg(name = "name", trailing_var_arg = true, num_args = 0..)]
         name_tokens: Vec<String>,
     },
+
+    /// Go back to the previously connected session
+    Last,
+
+    /// Go back N sessions in the connect history
+    Back {
+        /// How far back to go (1 is the same as `nitro last`)
+        #[arg(default_value_t = 1)]
+        n: usize,
+    },
+
+    /// Pin a directory or session so it is always listed first
+    Pin {
+        /// Directory, session name or `nitro list` line
+        #[arg(name = "target", trailing_var_arg = true, num_args = 1..)]
+        target: Vec<String>,
+    },
+
+    /// Remove a pin
+    Unpin {
+        /// Directory, session name or `nitro list` line
+        #[arg(name = "target", trailing_var_arg = true, num_args = 1..)]
+        target: Vec<String>,
+    },
+
+    /// Print pinned entries
+    Pins,
+
+    /// Snapshot all sessions so `nitro restore` can recreate them
+    Save {
+        /// Also record the command line running in each pane
+        #[arg(long = "commands", action = ArgAction::SetTrue)]
+        commands: bool,
+        /// Snapshot file (default: snapshot.json in the data directory)
+        #[arg(long = "file")]
+        file: Option<PathBuf>,
+    },
+
+    /// Recreate sessions from the last `nitro save`
+    Restore {
+        /// Snapshot file (default: snapshot.json in the data directory)
+        #[arg(long = "file")]
+        file: Option<PathBuf>,
+    },
+
+    /// Kill detached sessions that have been idle for too long
+    Gc {
+        /// Idle threshold, e.g. 12h or 7d (default: config, then 7d)
+        #[arg(long = "idle", value_name = "DURATION")]
+        idle: Option<String>,
+        /// Never kill sessions matching this pattern (repeatable)
+        #[arg(long = "exclude", value_name = "PATTERN")]
+        exclude: Vec<String>,
+        /// Also evict least recently used sessions beyond N in total
+        #[arg(long = "max", value_name = "N")]
+        max: Option<usize>,
+        /// Only print what would be killed
+        #[arg(long = "dry-run", action = ArgAction::SetTrue)]
+        dry_run: bool,
+    },
+
+    /// Pick entries in fzf and connect to them: preview, ctrl-x kill,
+    /// ctrl-r rename to the query, ctrl-t/ctrl-z/ctrl-a tmux/zoxide/all
+    Fzf {
+        /// Include pinned entries
+        #[arg(short = 'p', long = "pins", action = ArgAction::SetTrue)]
+        pins: bool,
+        /// Include tmux sessions
+        #[arg(short = 't', long = "tmux", action = ArgAction::SetTrue)]
+        tmux: bool,
+        /// Include zoxide results; optional N limits to top N
+        #[arg(short = 'z', long = "zoxide", num_args = 0..=1, value_parser = clap::value_parser!(usize))]
+        zoxide: Option<Option<usize>>,
+        /// Use fancy icons instead of ASCII [t]/[z]
+        #[arg(long = "icons", action = ArgAction::SetTrue)]
+        icons: bool,
+        /// Print the list for a reload (used by the bindings)
+        #[arg(long = "list", hide = true, group = "action")]
+        list: bool,
+        /// Print the preview of a list key (used by the bindings)
+        #[arg(long = "preview", value_name = "KEY", hide = true, group = "action")]
+        preview: Option<String>,
+        /// Kill the session of a list key (used by the bindings)
+        #[arg(long = "kill", value_name = "KEY", hide = true, group = "action")]
+        kill: Option<String>,
+        /// Rename the session of a list key (used by the bindings)
+        #[arg(
+            long = "rename",
+            value_names = ["KEY", "NAME"],
+            num_args = 2,
+            hide = true,
+            group = "action"
+        )]
+        rename: Option<Vec<String>>,
+    },
+
+    /// Print a segment for the tmux status line: position of the session,
+    /// other attached (+) and detached (~) sessions, next session, branch
+    Status {
+        /// Session to describe, e.g. '#S' (default: the active one)
+        #[arg(long = "session", value_name = "NAME")]
+        session: Option<String>,
+        /// Plain text instead of tmux #[...] styles
+        #[arg(long = "plain", action = ArgAction::SetTrue)]
+        plain: bool,
+        /// Reuse the segment for this long, e.g. 5s (default: config, then 5s; 0 never)
+        #[arg(long = "cache", value_name = "DURATION")]
+        cache: Option<String>,
+    },
+
+    /// Maintain the zoxide database
+    Zoxide {
+        #[command(subcommand)]
+        command: ZoxideCommands,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ZoxideCommands {
+    /// Remove entries whose directory no longer exists
+    Prune {
+        /// Only print what would be removed
+        #[arg(long = "dry-run", action = ArgAction::SetTrue)]
+        dry_run: bool,
+    },
 }
 
 #[cfg(test)]
@@ -54,15 +265,37 @@ mod tests {
         let c = Cli::parse_from(["nitro", "list"]);
         match c.command {
             Commands::List {
+                pins,
                 tmux,
                 zoxide,
                 icons,
-                no_color,
+                strict,
+                all_servers,
+                exclude,
+                keep_missing,
+                min_score,
+                scores,
+                json,
+                delimited,
+                long,
+                template,
+                types,
             } => {
+                assert!(types.is_empty());
+                assert!(!delimited);
+                assert!(!long);
+                assert!(template.is_empty());
+                assert!(min_score.is_none());
+                assert!(!scores);
+                assert!(!json);
+                assert!(exclude.is_empty());
+                assert!(!keep_missing);
+                assert!(!pins);
                 assert!(!tmux);
+                assert!(!strict);
+                assert!(!all_servers);
                 assert!(zoxide.is_none());
                 assert!(!icons);
-                assert!(!no_color);
             }
             _ => panic!("expected list"),
         }
@@ -81,6 +314,22 @@ mod tests {
             _ => panic!(),
         }
 
+        // score options
+        let c = Cli::parse_from(["nitro", "list", "-z", "5", "--min-score", "2.5", "--scores"]);
+        match c.command {
+            Commands::List {
+                zoxide,
+                min_score,
+                scores,
+                ..
+            } => {
+                assert_eq!(zoxide, Some(Some(5)));
+                assert_eq!(min_score, Some(2.5));
+                assert!(scores);
+            }
+            _ => panic!(),
+        }
+
         // zoxide presence without value => Some(None)
         let c = Cli::parse_from(["nitro", "list", "-z"]);
         match c.command {
@@ -89,6 +338,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_list_types() {
+        let c = Cli::parse_from(["nitro", "list", "--type", "rust", "--type", "go"]);
+        match c.command {
+            Commands::List { types, .. } => {
+                assert_eq!(types, [ProjectType::Rust, ProjectType::Go])
+            }
+            _ => panic!("expected list"),
+        }
+        // A usage error like any other bad value, not a runtime failure
+        let err = Cli::try_parse_from(["nitro", "list", "--type", "cobol"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::InvalidValue);
+        assert_eq!(err.exit_code(), 2);
+    }
+
     #[test]
     fn parse_connect_args_dir_and_name() {
         // dir flag
@@ -97,27 +361,33 @@ mod tests {
             Commands::Connect {
                 dir,
                 name_tokens,
-                no_color,
                 no_fail,
+                mkdir,
+                multi,
+                attach,
+                key,
             } => {
+                assert!(!key);
+                assert!(!multi);
+                assert!(attach.is_none());
+                assert!(!mkdir);
                 assert_eq!(dir.as_deref(), Some(std::path::Path::new("/tmp")));
                 assert_eq!(name_tokens, vec!["web".to_string(), "prod".to_string()]);
-                assert!(!no_color);
                 assert!(!no_fail);
             }
             _ => panic!("expected connect"),
         }
 
-        // no-color flag
+        // no-color flag, global but still accepted after the subcommand
         let c = Cli::parse_from(["nitro", "connect", "--no-color", "api"]);
+        assert!(c.no_color);
+        assert!(c.color.is_none());
         match c.command {
             Commands::Connect {
-                no_color,
                 name_tokens,
                 no_fail,
                 ..
             } => {
-                assert!(no_color);
                 assert!(!no_fail);
                 assert_eq!(name_tokens, vec!["api".to_string()]);
             }
@@ -132,5 +402,210 @@ mod tests {
             }
             _ => panic!(),
         }
+
+        // mkdir flag
+        let c = Cli::parse_from(["nitro", "connect", "--mkdir", "/new/dir"]);
+        match c.command {
+            Commands::Connect { mkdir, .. } => assert!(mkdir),
+            _ => panic!(),
+        }
+
+        // several lines, attach to a chosen one
+        let c = Cli::parse_from([
+            "nitro",
+            "connect",
+            "-m",
+            "--attach",
+            "web",
+            "[t] api",
+            "[z] /src/web",
+        ]);
+        match c.command {
+            Commands::Connect {
+                multi,
+                attach,
+                name_tokens,
+                ..
+            } => {
+                assert!(multi);
+                assert_eq!(attach.as_deref(), Some("web"));
+                assert_eq!(name_tokens, vec!["[t] api", "[z] /src/web"]);
+            }
+            _ => panic!(),
+        }
+
+        let c = Cli::parse_from(["nitro", "connect", "--key", "t::api\t[t] api"]);
+        match c.command {
+            Commands::Connect { key, .. } => assert!(key),
+            _ => panic!(),
+        }
+
+        // no name: lines come from stdin
+        let c = Cli::parse_from(["nitro", "connect"]);
+        match c.command {
+            Commands::Connect { name_tokens, .. } => assert!(name_tokens.is_empty()),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_global_socket_flags() {
+        let c = Cli::parse_from(["nitro", "list", "-L", "work", "--all-servers"]);
+        assert_eq!(c.socket_name.as_deref(), Some("work"));
+        assert!(c.socket_path.is_none());
+        match c.command {
+            Commands::List { all_servers, .. } => assert!(all_servers),
+            _ => panic!(),
+        }
+
+        let c = Cli::parse_from(["nitro", "-S", "/tmp/s", "connect", "api"]);
+        assert_eq!(
+            c.socket_path.as_deref(),
+            Some(std::path::Path::new("/tmp/s"))
+        );
+
+        assert!(Cli::try_parse_from(["nitro", "-L", "a", "-S", "/b", "list"]).is_err());
+    }
+
+    #[test]
+    fn parse_pin_commands() {
+        let c = Cli::parse_from(["nitro", "pin", "[z]", "/src/api"]);
+        match c.command {
+            Commands::Pin { target } => assert_eq!(target, vec!["[z]", "/src/api"]),
+            _ => panic!("expected pin"),
+        }
+        let c = Cli::parse_from(["nitro", "unpin", "notes"]);
+        assert!(matches!(c.command, Commands::Unpin { .. }));
+        let c = Cli::parse_from(["nitro", "pins"]);
+        assert!(matches!(c.command, Commands::Pins));
+        let c = Cli::parse_from(["nitro", "list", "-p"]);
+        match c.command {
+            Commands::List { pins, .. } => assert!(pins),
+            _ => panic!(),
+        }
+        let c = Cli::parse_from(["nitro", "list", "--exclude", "/tmp/**", "--exclude", "_*"]);
+        match c.command {
+            Commands::List { exclude, .. } => assert_eq!(exclude, vec!["/tmp/**", "_*"]),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_zoxide_prune() {
+        let c = Cli::parse_from(["nitro", "zoxide", "prune", "--dry-run"]);
+        match c.command {
+            Commands::Zoxide {
+                command: ZoxideCommands::Prune { dry_run },
+            } => assert!(dry_run),
+            _ => panic!("expected zoxide prune"),
+        }
+    }
+
+    #[test]
+    fn parse_last_and_back() {
+        assert!(matches!(
+            Cli::parse_from(["nitro", "last"]).command,
+            Commands::Last
+        ));
+        match Cli::parse_from(["nitro", "back"]).command {
+            Commands::Back { n } => assert_eq!(n, 1),
+            _ => panic!("expected back"),
+        }
+        match Cli::parse_from(["nitro", "back", "3"]).command {
+            Commands::Back { n } => assert_eq!(n, 3),
+            _ => panic!("expected back"),
+        }
+    }
+
+    #[test]
+    fn parse_gc() {
+        let c = Cli::parse_from([
+            "nitro",
+            "gc",
+            "--idle",
+            "3d",
+            "--exclude",
+            "_*",
+            "--max",
+            "20",
+            "--dry-run",
+        ]);
+        match c.command {
+            Commands::Gc {
+                idle,
+                exclude,
+                max,
+                dry_run,
+            } => {
+                assert_eq!(idle.as_deref(), Some("3d"));
+                assert_eq!(exclude, vec!["_*"]);
+                assert_eq!(max, Some(20));
+                assert!(dry_run);
+            }
+            _ => panic!("expected gc"),
+        }
+    }
+
+    #[test]
+    fn parse_fzf() {
+        let c = Cli::parse_from(["nitro", "fzf", "-z", "--icons"]);
+        match c.command {
+            Commands::Fzf {
+                zoxide,
+                icons,
+                list,
+                rename,
+                ..
+            } => {
+                assert_eq!(zoxide, Some(None));
+                assert!(icons);
+                assert!(!list);
+                assert!(rename.is_none());
+            }
+            _ => panic!("expected fzf"),
+        }
+        let c = Cli::parse_from(["nitro", "fzf", "-t", "-z", "--rename", "t::api", ""]);
+        match c.command {
+            Commands::Fzf { zoxide, rename, .. } => {
+                assert_eq!(zoxide, Some(None));
+                assert_eq!(rename, Some(vec!["t::api".into(), "".into()]));
+            }
+            _ => panic!("expected fzf"),
+        }
+        assert!(Cli::try_parse_from(["nitro", "fzf", "--list", "--kill", "t::a"]).is_err());
+    }
+
+    #[test]
+    fn parse_status() {
+        let c = Cli::parse_from(["nitro", "status", "--session", "#S", "--cache", "0"]);
+        match c.command {
+            Commands::Status {
+                session,
+                plain,
+                cache,
+            } => {
+                assert_eq!(session.as_deref(), Some("#S"));
+                assert!(!plain);
+                assert_eq!(cache.as_deref(), Some("0"));
+            }
+            _ => panic!("expected status"),
+        }
+    }
+
+    #[test]
+    fn parse_save_and_restore() {
+        let c = Cli::parse_from(["nitro", "save", "--commands"]);
+        match c.command {
+            Commands::Save { commands, file } => {
+                assert!(commands);
+                assert!(file.is_none());
+            }
+            _ => panic!("expected save"),
+        }
+        let c = Cli::parse_from(["nitro", "restore", "--file", "/tmp/s.json"]);
+        match c.command {
+            Commands::Restore { file } => assert_eq!(file, Some(PathBuf::from("/tmp/s.json"))),
+            _ => panic!("expected restore"),
+        }
     }
 }
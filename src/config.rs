@@ -0,0 +1,316 @@
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// User configuration, read from `$NITRO_CONFIG` or
+/// `<config dir>/nitro/config.toml`. Every field is optional; a missing file
+/// is the same as an empty one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct Config {
+    pub(crate) tmux: TmuxConfig,
+    pub(crate) connect: ConnectConfig,
+    pub(crate) filter: FilterConfig,
+    pub(crate) hooks: HooksConfig,
+    pub(crate) env: EnvConfig,
+    pub(crate) refresh_env: RefreshEnvConfig,
+    pub(crate) gc: GcConfig,
+    pub(crate) template: TemplateConfig,
+    pub(crate) theme: ThemeConfig,
+    pub(crate) status: StatusConfig,
+    pub(crate) fzf: FzfConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct TmuxConfig {
+    /// Server socket name, as with `tmux -L`
+    pub socket_name: Option<String>,
+    /// Server socket path, as with `tmux -S`
+    pub socket_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct ConnectConfig {
+    /// Where `nitro connect <git url>` clones repositories (default `~/src`)
+    pub projects_root: Option<PathBuf>,
+}
+
+/// Shell commands run by `nitro connect` around session creation and attach
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct HooksConfig {
+    pub pre_create: Option<String>,
+    pub post_create: Option<String>,
+    pub pre_attach: Option<String>,
+    pub post_detach: Option<String>,
+}
+
+/// Environment loaded into new sessions from the session directory
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct EnvConfig {
+    /// Load `dotenv_file` (default `.env`)
+    pub dotenv: bool,
+    pub dotenv_file: Option<String>,
+    /// Apply `direnv export json`
+    pub direnv: bool,
+}
+
+/// Variables refreshed in an existing session when attaching from outside tmux
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct RefreshEnvConfig {
+    pub vars: Vec<String>,
+    /// Also type `export` lines into panes sitting at a shell prompt
+    pub broadcast: bool,
+}
+
+impl Default for RefreshEnvConfig {
+    fn default() -> Self {
+        RefreshEnvConfig {
+            vars: [
+                "SSH_AUTH_SOCK",
+                "SSH_CONNECTION",
+                "DISPLAY",
+                "WAYLAND_DISPLAY",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            broadcast: false,
+        }
+    }
+}
+
+/// Defaults for `nitro gc`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct GcConfig {
+    /// Idle threshold such as `12h` or `7d` (default `7d`)
+    pub idle: Option<String>,
+    /// Session name patterns that are never killed
+    pub exclude: Vec<String>,
+    /// Keep at most this many sessions, evicting the least recently used
+    pub max_sessions: Option<usize>,
+}
+
+/// `nitro list` line templates: `default` for every source, or one per source
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct TemplateConfig {
+    pub default: Option<String>,
+    pub pin: Option<String>,
+    pub tmux: Option<String>,
+    pub zoxide: Option<String>,
+}
+
+/// Colors for nitro's output
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct ThemeConfig {
+    /// `auto` (default), `always` or `never`; `--color` overrides it
+    pub color: Option<String>,
+    /// Built-in palette: `default`, `nord`, `gruvbox` or `mono`
+    pub name: Option<String>,
+    /// Marker colors per source: a name, a 256-color index or `#rrggbb`
+    pub pin: Option<String>,
+    pub tmux: Option<String>,
+    pub zoxide: Option<String>,
+    /// Sessions unused for this long (e.g. `3d`) are dimmed (default `7d`)
+    pub stale: Option<String>,
+}
+
+/// Defaults for `nitro status`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct StatusConfig {
+    /// How long a segment is reused, such as `5s` (default); `0` never
+    pub cache: Option<String>,
+    /// Print plain text instead of tmux styles
+    pub plain: bool,
+}
+
+/// Settings for `nitro fzf`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct FzfConfig {
+    /// Extra fzf arguments, e.g. `["--height=40%", "--layout=reverse"]`
+    pub options: Vec<String>,
+}
+
+/// Include/exclude patterns per `nitro list` source
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct FilterConfig {
+    /// Matched against session names
+    pub tmux: RuleConfig,
+    /// Matched against directory paths
+    pub zoxide: RuleConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub(crate) struct RuleConfig {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// Where nitro keeps its own state (pins, snapshots, history):
+/// `$NITRO_DATA_DIR`, or `<data dir>/nitro`.
+pub(crate) fn data_dir<S: Shell>(sh: &S) -> Option<PathBuf> {
+    if let Some(p) = sh.env_var("NITRO_DATA_DIR").filter(|s| !s.is_empty()) {
+        return Some(PathBuf::from(p));
+    }
+    dirs_next::data_dir().map(|d| d.join("nitro"))
+}
+
+/// Where nitro keeps files it can rebuild (the status cache):
+/// `$NITRO_CACHE_DIR`, or `<cache dir>/nitro`.
+pub(crate) fn cache_dir<S: Shell>(sh: &S) -> Option<PathBuf> {
+    if let Some(p) = sh.env_var("NITRO_CACHE_DIR").filter(|s| !s.is_empty()) {
+        return Some(PathBuf::from(p));
+    }
+    dirs_next::cache_dir().map(|d| d.join("nitro"))
+}
+
+/// Write a cache file through a temporary file, so processes reading it at
+/// the same time never see half of it.
+pub(crate) fn write_cache(path: &Path, text: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    std::fs::write(&tmp, text).with_context(|| format!("failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("failed to write {}", path.display()))
+}
+
+pub(crate) fn config_path<S: Shell>(sh: &S) -> Option<PathBuf> {
+    if let Some(p) = sh.env_var("NITRO_CONFIG").filter(|s| !s.is_empty()) {
+        return Some(PathBuf::from(p));
+    }
+    dirs_next::config_dir().map(|d| d.join("nitro").join("config.toml"))
+}
+
+/// Parse `config.toml` text.
+pub fn parse(text: &str) -> Result<Config> {
+    Ok(toml::from_str(text)?)
+}
+
+/// Read the config file (see [`Config`]); a missing file gives the
+/// defaults.
+pub fn load<S: Shell>(sh: &S) -> Result<Config> {
+    let Some(path) = config_path(sh) else {
+        return Ok(Config::default());
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("failed to read {}", path.display()));
+        }
+    };
+    parse(&text).with_context(|| format!("invalid config {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_empty_and_tmux_section() -> Result<()> {
+        let c = parse("")?;
+        assert!(c.tmux.socket_name.is_none());
+        assert!(c.tmux.socket_path.is_none());
+
+        let c = parse("[tmux]\nsocket_name = \"work\"\n")?;
+        assert_eq!(c.tmux.socket_name.as_deref(), Some("work"));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_filter_sections() -> Result<()> {
+        let c = parse(
+            "[filter.tmux]\nexclude = [\"_popup*\"]\n[filter.zoxide]\ninclude = [\"~/src/**\"]\n",
+        )?;
+        assert_eq!(c.filter.tmux.exclude, vec!["_popup*"]);
+        assert!(c.filter.tmux.include.is_empty());
+        assert_eq!(c.filter.zoxide.include, vec!["~/src/**"]);
+        Ok(())
+    }
+
+    #[test]
+    fn refresh_env_defaults_and_override() -> Result<()> {
+        let c = parse("")?;
+        assert!(c.refresh_env.vars.contains(&"SSH_AUTH_SOCK".to_string()));
+        assert!(!c.refresh_env.broadcast);
+        let c = parse("[refresh_env]\nvars = []\nbroadcast = true\n")?;
+        assert!(c.refresh_env.vars.is_empty());
+        assert!(c.refresh_env.broadcast);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_gc_section() -> Result<()> {
+        let c = parse("[gc]\nidle = \"3d\"\nexclude = [\"main\"]\nmax_sessions = 12\n")?;
+        assert_eq!(c.gc.idle.as_deref(), Some("3d"));
+        assert_eq!(c.gc.exclude, vec!["main"]);
+        assert_eq!(c.gc.max_sessions, Some(12));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_template_section() -> Result<()> {
+        let c = parse("[template]\ndefault = \"{icon} {name}\"\ntmux = \"{name} {windows}w\"\n")?;
+        assert_eq!(c.template.default.as_deref(), Some("{icon} {name}"));
+        assert_eq!(c.template.tmux.as_deref(), Some("{name} {windows}w"));
+        assert!(c.template.zoxide.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_theme_section() -> Result<()> {
+        let c = parse("[theme]\ncolor = \"always\"\nname = \"nord\"\ntmux = \"#ff8800\"\n")?;
+        assert_eq!(c.theme.color.as_deref(), Some("always"));
+        assert_eq!(c.theme.name.as_deref(), Some("nord"));
+        assert_eq!(c.theme.tmux.as_deref(), Some("#ff8800"));
+        assert!(c.theme.stale.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_status_section() -> Result<()> {
+        let c = parse("[status]\ncache = \"10s\"\nplain = true\n")?;
+        assert_eq!(c.status.cache.as_deref(), Some("10s"));
+        assert!(c.status.plain);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_fzf_section() -> Result<()> {
+        let c = parse("[fzf]\noptions = [\"--height=40%\"]\n")?;
+        assert_eq!(c.fzf.options, ["--height=40%"]);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_rejects_wrong_types() {
+        assert!(parse("[tmux]\nsocket_name = 3\n").is_err());
+    }
+}
@@ -1,48 +1,131 @@
+use crate::config::{Config, EnvConfig, HooksConfig, RefreshEnvConfig};
+use crate::error::{self, NitroError};
+use crate::git;
+use crate::history;
+use crate::hooks::{self, Hook, HookContext};
+use crate::list::{self, Source};
+use crate::paths;
+use crate::session_env;
 use crate::shell::Shell;
 use crate::tmux;
 use crate::zoxide;
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
 
+/// Input and settings for [`run_connect`].
+#[non_exhaustive]
 pub struct ConnectOptions {
+    /// The list line, as whitespace-split tokens
     pub tokens: Vec<String>,
+    /// Start directory for a new session instead of the line's path
     pub dir: Option<PathBuf>,
+    /// Create a missing session directory without asking
+    pub mkdir: bool,
+    /// Clone destination for git URLs; defaults to `~/src`
+    pub(crate) projects_root: Option<PathBuf>,
+    /// Commands run around session creation and attach
+    pub(crate) hooks: HooksConfig,
+    /// Environment (.env, direnv) applied to new sessions
+    pub(crate) env: EnvConfig,
+    /// Variables refreshed in existing sessions when attaching
+    pub(crate) refresh_env: RefreshEnvConfig,
+    /// Record attached sessions for `nitro last`/`nitro back`
+    pub history: bool,
+    /// Treat every token as a separate list line
+    pub multi: bool,
+    /// With several lines, the session to attach to instead of the first
+    pub attach: Option<String>,
+    /// Lines are `nitro list --delimited` keys, resolved exactly
+    pub key: bool,
+    /// Replaying a transcript: session directories are neither checked nor
+    /// created, since they belong to the machine that recorded it
+    pub(crate) replay: bool,
 }
 
+impl ConnectOptions {
+    /// Options for connecting to the list line `tokens`, with the connect,
+    /// hooks and environment settings of `cfg`. History is not recorded.
+    pub fn new(tokens: Vec<String>, cfg: &Config) -> ConnectOptions {
+        ConnectOptions {
+            tokens,
+            dir: None,
+            mkdir: false,
+            projects_root: cfg.connect.projects_root.clone(),
+            hooks: cfg.hooks.clone(),
+            env: cfg.env.clone(),
+            refresh_env: cfg.refresh_env.clone(),
+            history: false,
+            multi: false,
+            attach: None,
+            key: false,
+            replay: false,
+        }
+    }
+}
+
+/// A `nitro list` line taken apart by [`parse_connect_line`].
+#[non_exhaustive]
 pub struct ParsedLine {
+    /// Session name, already normalized
     pub name: String,
+    /// Directory for a new session
     pub path: Option<PathBuf>,
+    /// tmux server socket name, from `nitro list --all-servers` lines
+    pub server: Option<String>,
+    /// `name` is the basename of `path` rather than given in the line
+    pub name_from_path: bool,
 }
 
+fn is_icon(c: char) -> bool {
+    matches!(c, '\u{e000}'..='\u{f8ff}' | '\u{f0000}'..)
+}
+
+/// Take apart a `nitro list` line (source marker, session name, path and
+/// `server:` label), given as whitespace-split tokens.
 pub fn parse_connect_line(tokens: &[String]) -> ParsedLine {
     // Reassemble full line to robustly strip icons and parse path
     let mut s = tokens.join(" ").trim().to_string();
+    let mut is_tmux_line = false;
+
+    // Strip a trailing score annotation like " [12.5]" (nitro list --scores)
+    if let Some(open) = s.rfind(" [")
+        && let Some(inner) = s[open + 2..].strip_suffix(']')
+        && inner.parse::<f64>().is_ok()
+    {
+        s.truncate(open);
+    }
 
     // Strip leading icon like "[t] " or "[z] " if present
     if let Some(pos) = s.find(']')
         && s.starts_with('[')
         && pos < s.len()
     {
+        is_tmux_line = &s[..pos] == "[t";
         let after = &s[pos + 1..];
         let after = after.strip_prefix(' ').unwrap_or(after);
         s = after.to_string();
     }
-    // Strip fancy icons ( or ) with one or two spaces after
-    if let Some(after) = s.strip_prefix("  ") {
-        s = after.to_string();
-    } else if let Some(after) = s.strip_prefix("  ") {
-        s = after.to_string();
-    } else if let Some(after) = s.strip_prefix(" ") {
-        s = after.to_string();
-    } else if let Some(after) = s.strip_prefix(" ") {
-        s = after.to_string();
+    // Strip a Nerd Font glyph (the source icon, or a project type's) with
+    // the spaces after it; the glyphs live in the private use areas
+    if let Some(c) = s.chars().next().filter(|c| is_icon(*c)) {
+        is_tmux_line = s.starts_with(list::ICON_TMUX);
+        s = s[c.len_utf8()..].trim_start().to_string();
+    }
+
+    // tmux lines listed across servers read "server:session"; tmux session
+    // names cannot contain ':' so the split is unambiguous.
+    let mut server = None;
+    if is_tmux_line && let Some((srv, rest)) = s.split_once(':') {
+        server = Some(srv.trim().to_string());
+        s = rest.trim().to_string();
     }
 
-    // Split and find the first absolute path token
+    // Split and find the first path token. Session names may start with
+    // `~`, `$` or `.`, so tmux lines only take absolute paths.
     let parts: Vec<&str> = s.split_whitespace().collect();
     let mut split_idx: Option<usize> = None;
     for (i, part) in parts.iter().enumerate() {
-        if part.starts_with('/') {
+        if part.starts_with('/') || (!is_tmux_line && paths::looks_like_path(part)) {
             split_idx = Some(i);
             break;
         }
@@ -59,22 +142,43 @@ pub fn parse_connect_line(tokens: &[String]) -> ParsedLine {
 
     // If no explicit name but we have a path, derive name from basename
     let mut name = normalize_name(&name_str);
-    if (name.is_empty() || name == "-")
-        && path_opt.is_some()
-        && let Some(base) = path_opt
+    let name_from_path = (name.is_empty() || name == "-") && path_opt.is_some();
+    if name_from_path {
+        name = path_opt
             .as_ref()
             .and_then(|p| p.file_name())
-            .map(|s| s.to_string_lossy().to_string())
-    {
-        name = normalize_name(&base);
+            .map(|s| normalize_name(&s.to_string_lossy()))
+            .unwrap_or_default();
     }
 
     ParsedLine {
         name,
         path: path_opt,
+        server,
+        name_from_path,
     }
 }
 
+/// Expand `~`, `~user`, `$VAR` and relative paths in a parsed line and
+/// resolve symlinks, so every spelling of a directory opens one session. A
+/// name taken from the path follows the canonical basename.
+pub(crate) fn expand_line<S: Shell>(sh: &S, mut line: ParsedLine) -> Result<ParsedLine> {
+    let Some(raw) = &line.path else {
+        return Ok(line);
+    };
+    let path = paths::normalize(&paths::expand(sh, &raw.to_string_lossy())?);
+    if line.name_from_path {
+        line.name = path
+            .file_name()
+            .map(|s| normalize_name(&s.to_string_lossy()))
+            .unwrap_or_default();
+    }
+    line.path = Some(path);
+    Ok(line)
+}
+
+/// Turn text into a valid tmux session name: whitespace runs become one
+/// `-`, and `:`/`#` (special in tmux targets) become `-`.
 pub fn normalize_name(s: &str) -> String {
     let trimmed = s.trim();
     let mut out = String::with_capacity(trimmed.len());
@@ -98,77 +202,433 @@ pub fn normalize_name(s: &str) -> String {
     out.trim_matches('-').to_string()
 }
 
+/// Split connect arguments into list lines: with `multi` every argument is
+/// a line of its own, otherwise they form one line. Embedded newlines (e.g.
+/// from `"$(fzf --multi)"`) always separate lines.
+pub(crate) fn connect_lines(tokens: &[String], multi: bool) -> Vec<String> {
+    let lines: Vec<String> = if multi {
+        tokens.to_vec()
+    } else {
+        vec![tokens.join(" ")]
+    };
+    lines
+        .iter()
+        .flat_map(|l| l.lines())
+        .filter(|l| !l.trim().is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Resolve a `nitro list --delimited` line (or just its key) exactly: the
+/// key before the first tab names the source and the exact session or
+/// path, so nothing is guessed from whitespace.
+pub(crate) fn parse_key_line(line: &str) -> Result<ParsedLine> {
+    let key = line.split('\t').next().unwrap_or("");
+    let key = list::parse_key(key).with_context(|| format!("invalid list key {:?}", key))?;
+    let from_path = |p: &str| {
+        let path = PathBuf::from(p);
+        let name = path
+            .file_name()
+            .map(|s| normalize_name(&s.to_string_lossy()))
+            .unwrap_or_default();
+        (name, Some(path))
+    };
+    let (name, path) = match key.source {
+        // Existing sessions are addressed by their exact name
+        Source::Tmux => (key.id, None),
+        Source::Pin if !key.id.starts_with('/') => (normalize_name(&key.id), None),
+        _ => from_path(&key.id),
+    };
+    if name.is_empty() {
+        return Err(NitroError::EmptySessionName.into());
+    }
+    Ok(ParsedLine {
+        name,
+        path,
+        server: key.server,
+        name_from_path: false,
+    })
+}
+
+/// Attach to (or switch to) the session for `opts.tokens`, creating it
+/// first if needed. Git URLs are cloned, and several lines open several
+/// sessions.
 pub fn run_connect<S: Shell>(sh: &S, opts: ConnectOptions) -> Result<()> {
-    let ParsedLine { name, path } = parse_connect_line(&opts.tokens);
+    // Outside tmux the connect ends in `attach`; fail before creating
+    // sessions nobody can attach to
+    if sh.env_var("TMUX").is_none() && !sh.has_terminal() {
+        return Err(NitroError::NotATerminal.into());
+    }
+    let lines = connect_lines(&opts.tokens, opts.multi);
+    if lines.len() > 1 {
+        return connect_many(sh, &opts, &lines);
+    }
+    if opts.attach.is_some() {
+        anyhow::bail!("--attach chooses among several lines, but there is only one");
+    }
+    if opts.key {
+        let line = lines.first().map(String::as_str).unwrap_or("");
+        let ParsedLine {
+            name, path, server, ..
+        } = parse_key_line(line)?;
+        return connect_on(sh, &opts, server.as_deref(), name, path, "line");
+    }
+    let line = opts.tokens.join(" ");
+    if git::is_git_url(line.trim()) {
+        return connect_clone(sh, &opts, line.trim());
+    }
+
+    let ParsedLine {
+        name, path, server, ..
+    } = expand_line(sh, parse_connect_line(&opts.tokens))?;
+    if name.is_empty() {
+        return Err(NitroError::EmptySessionName.into());
+    }
+    connect_on(sh, &opts, server.as_deref(), name, path, "line")
+}
+
+/// A session to open: (server, name, path, path source).
+type Target = (Option<String>, String, Option<PathBuf>, &'static str);
+
+fn resolve_line<S: Shell>(sh: &S, opts: &ConnectOptions, line: &str) -> Result<Target> {
+    if opts.key {
+        let ParsedLine {
+            name, path, server, ..
+        } = parse_key_line(line)?;
+        return Ok((server, name, path, "line"));
+    }
+    let line = line.trim();
+    if git::is_git_url(line) {
+        let (name, dest) = clone_target(sh, opts, line)?;
+        return Ok((None, name, Some(dest), "clone"));
+    }
+    let tokens: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+    let ParsedLine {
+        name, path, server, ..
+    } = expand_line(sh, parse_connect_line(&tokens))?;
     if name.is_empty() {
-        anyhow::bail!("empty session name");
+        return Err(NitroError::EmptySessionName.into());
     }
+    Ok((server, name, path, "line"))
+}
+
+/// Open every line's session, creating missing ones detached, then attach
+/// to `opts.attach` (a session name) or the first one. A line that fails is
+/// reported and skipped.
+fn connect_many<S: Shell>(sh: &S, opts: &ConnectOptions, lines: &[String]) -> Result<()> {
+    let mut first_err = None;
+    let mut fail = |line: &str, e: anyhow::Error| {
+        let e = e.context(format!("cannot open {}", line.trim()));
+        error::warn(&e);
+        first_err.get_or_insert(e);
+    };
+    let mut targets = Vec::new();
+    for line in lines {
+        match resolve_line(sh, opts, line) {
+            Ok(t) => targets.push((line, t)),
+            Err(e) => fail(line, e),
+        }
+    }
+    // Settle the session to attach to before creating any
+    let chosen = match &opts.attach {
+        Some(want) => {
+            let normalized = normalize_name(want);
+            let i = targets
+                .iter()
+                .position(|(_, (_, name, ..))| *name == *want || *name == normalized)
+                .with_context(|| format!("{} is not among the selected sessions", want))?;
+            Some(i)
+        }
+        None => None,
+    };
+    let mut ready: Vec<(usize, Option<String>, Ready)> = Vec::new();
+    for (i, (line, (server, name, path, source))) in targets.into_iter().enumerate() {
+        match ensure_on(sh, opts, server.as_deref(), name, path, source) {
+            Ok(r) => ready.push((i, server, r)),
+            Err(e) => fail(line, e),
+        }
+    }
+    let found = match chosen {
+        Some(i) => ready.iter().find(|(j, ..)| *j == i),
+        None => ready.first(),
+    };
+    let Some((_, server, r)) = found else {
+        return Err(first_err.unwrap_or_else(|| NitroError::EmptySessionName.into()));
+    };
+    match server {
+        Some(server) => {
+            let sh = tmux::WithSocket::new(sh, &tmux::Socket::Name(server.clone()));
+            attach_ready(&sh, opts, Some(server), r)
+        }
+        None => attach_ready(sh, opts, None, r),
+    }
+}
 
+/// Connect to `name` on the tmux server with socket name `server`, or on
+/// the current one.
+pub(crate) fn connect_on<S: Shell>(
+    sh: &S,
+    opts: &ConnectOptions,
+    server: Option<&str>,
+    name: String,
+    path: Option<PathBuf>,
+    path_source: &str,
+) -> Result<()> {
+    match server {
+        Some(server) => {
+            let sh = tmux::WithSocket::new(sh, &tmux::Socket::Name(server.to_string()));
+            let r = ensure_session(&sh, opts, name, path, path_source)?;
+            attach_ready(&sh, opts, Some(server), &r)
+        }
+        None => {
+            let r = ensure_session(sh, opts, name, path, path_source)?;
+            attach_ready(sh, opts, None, &r)
+        }
+    }
+}
+
+fn ensure_on<S: Shell>(
+    sh: &S,
+    opts: &ConnectOptions,
+    server: Option<&str>,
+    name: String,
+    path: Option<PathBuf>,
+    path_source: &str,
+) -> Result<Ready> {
+    match server {
+        Some(server) => {
+            let sh = tmux::WithSocket::new(sh, &tmux::Socket::Name(server.to_string()));
+            ensure_session(&sh, opts, name, path, path_source)
+        }
+        None => ensure_session(sh, opts, name, path, path_source),
+    }
+}
+
+/// A session that exists and can be attached to.
+struct Ready {
+    name: String,
+    dir: String,
+    /// Where `dir` came from, as in `HookContext::source`
+    source: String,
+}
+
+/// Create the session (detached) unless it exists. `path_source` names
+/// where `path` came from, for hooks' `NITRO_SOURCE`.
+fn ensure_session<S: Shell>(
+    sh: &S,
+    opts: &ConnectOptions,
+    name: String,
+    path: Option<PathBuf>,
+    path_source: &str,
+) -> Result<Ready> {
     if tmux::has_session(sh, &name).unwrap_or(false) {
-        tmux::attach_or_switch(sh, &name)?;
-        return Ok(());
+        // Only ask tmux for the directory when a hook will see it
+        let dir = if opts.hooks.pre_attach.is_some() || opts.hooks.post_detach.is_some() {
+            tmux::session_path(sh, &name).ok().flatten()
+        } else {
+            None
+        };
+        return Ok(Ready {
+            name,
+            dir: dir.unwrap_or_default(),
+            source: "existing".to_string(),
+        });
     }
 
     // pick dir: --dir > parsed path > zoxide best match > $HOME
-    let dir = if let Some(d) = &opts.dir {
-        d.clone()
+    let (dir, source) = if let Some(d) = &opts.dir {
+        (d.clone(), "dir")
     } else if let Some(p) = path {
-        p
+        (p, path_source)
     } else if let Some(p) = zoxide::best_match_dir(sh, &name) {
-        p
+        (p, "zoxide")
     } else {
-        dirs_next::home_dir().unwrap_or_else(|| PathBuf::from("."))
+        let home = dirs_next::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        (home, "home")
     };
 
-    tmux::create_session(sh, &name, &dir.to_string_lossy())?;
-    tmux::attach_or_switch(sh, &name)?;
+    if !opts.replay {
+        ensure_dir(sh, &dir, opts.mkdir)?;
+    }
+    let dir = dir.to_string_lossy();
+    let ctx = HookContext {
+        session: &name,
+        dir: &dir,
+        source,
+    };
+    hooks::run(sh, &opts.hooks, Hook::PreCreate, &ctx)?;
+    let env = session_env::collect(sh, &opts.env, Path::new(dir.as_ref()));
+    tmux::create_session(sh, &name, &dir, &env)?;
+    hooks::run(sh, &opts.hooks, Hook::PostCreate, &ctx)?;
+    Ok(Ready {
+        dir: dir.to_string(),
+        source: source.to_string(),
+        name,
+    })
+}
+
+fn attach_ready<S: Shell>(
+    sh: &S,
+    opts: &ConnectOptions,
+    server: Option<&str>,
+    r: &Ready,
+) -> Result<()> {
+    if r.source == "existing" {
+        refresh_env(sh, opts, &r.name);
+    }
+    let ctx = HookContext {
+        session: &r.name,
+        dir: &r.dir,
+        source: &r.source,
+    };
+    attach(sh, opts, server, &ctx)
+}
+
+/// A new terminal or SSH login brings fresh SSH_AUTH_SOCK, DISPLAY, ...;
+/// push them into the session before attaching. Inside tmux nitro's own
+/// environment is the pane's, which is no fresher, so nothing is done.
+fn refresh_env<S: Shell>(sh: &S, opts: &ConnectOptions, name: &str) {
+    let vars = &opts.refresh_env.vars;
+    if vars.is_empty() || sh.env_var("TMUX").is_some() {
+        return;
+    }
+    let mut res = tmux::refresh_environment(sh, name, vars);
+    if res.is_ok() && opts.refresh_env.broadcast {
+        res = tmux::broadcast_environment(sh, name, vars);
+    }
+    if let Err(e) = res {
+        error::warn(&e.context("failed to refresh session environment"));
+    }
+}
+
+/// Attach (or switch) with the attach hooks around it. `post_detach` only
+/// runs when nitro itself attached, i.e. outside tmux.
+fn attach<S: Shell>(
+    sh: &S,
+    opts: &ConnectOptions,
+    server: Option<&str>,
+    ctx: &HookContext,
+) -> Result<()> {
+    hooks::run(sh, &opts.hooks, Hook::PreAttach, ctx)?;
+    if opts.history {
+        // Recorded before attaching: outside tmux, attach only returns on detach
+        if let Err(e) = history::record(sh, server, ctx.session) {
+            error::warn(&e.context("failed to record connect history"));
+        }
+    }
+    tmux::attach_or_switch(sh, ctx.session)?;
+    if sh.env_var("TMUX").is_none() {
+        hooks::run(sh, &opts.hooks, Hook::PostDetach, ctx)?;
+    }
     Ok(())
 }
 
+/// Clone `url` into the projects root (unless already there) and connect to
+/// a session named after the repository.
+fn connect_clone<S: Shell>(sh: &S, opts: &ConnectOptions, url: &str) -> Result<()> {
+    let (name, dest) = clone_target(sh, opts, url)?;
+    connect_on(sh, opts, None, name, Some(dest), "clone")
+}
+
+/// Session name and local checkout for a git URL, cloning it if needed.
+fn clone_target<S: Shell>(sh: &S, opts: &ConnectOptions, url: &str) -> Result<(String, PathBuf)> {
+    let repo = git::repo_name(url).with_context(|| format!("no repository name in {}", url))?;
+    let name = normalize_name(&repo);
+    if name.is_empty() {
+        return Err(NitroError::EmptySessionName.into());
+    }
+    let dest = match &opts.dir {
+        Some(d) => d.clone(),
+        None => projects_root(sh, opts)?.join(&repo),
+    };
+    if !dest.exists() {
+        git::clone(sh, url, &dest)?;
+    }
+    Ok((name, dest))
+}
+
+fn projects_root<S: Shell>(sh: &S, opts: &ConnectOptions) -> Result<PathBuf> {
+    match &opts.projects_root {
+        Some(p) => paths::expand(sh, &p.to_string_lossy()),
+        None => paths::expand(sh, "~/src"),
+    }
+}
+
+/// Make sure a new session's directory exists, creating it when `mkdir` is
+/// set or the user agrees; tmux would otherwise fail with an opaque error.
+fn ensure_dir<S: Shell>(sh: &S, dir: &Path, mkdir: bool) -> Result<()> {
+    if dir.is_dir() {
+        return Ok(());
+    }
+    if dir.exists() {
+        anyhow::bail!("{} is not a directory", dir.display());
+    }
+    if mkdir
+        || sh.confirm(&format!(
+            "Directory {} does not exist. Create it?",
+            dir.display()
+        ))
+    {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        return Ok(());
+    }
+    Err(NitroError::MissingDirectory(dir.to_path_buf()).into())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transcript::{Call, ReplayShell};
     use anyhow::Result;
-    use std::collections::HashMap;
 
-    #[derive(Default)]
-    struct MockShell {
-        status: HashMap<(String, Vec<String>), bool>,
-        env: HashMap<String, String>,
+    /// Commands nobody scripted succeed, so tests only script what they
+    /// check.
+    fn mock() -> ReplayShell {
+        ReplayShell::default().lenient()
     }
 
-    impl MockShell {
-        fn status(mut self, program: &str, args: &[&str], ok: bool) -> Self {
-            self.status.insert(
-                (program.into(), args.iter().map(|s| s.to_string()).collect()),
-                ok,
-            );
-            self
-        }
-        fn with_env(mut self, k: &str, v: &str) -> Self {
-            self.env.insert(k.into(), v.into());
-            self
-        }
+    /// The `run` calls made so far, as (program, args).
+    fn runs(sh: &ReplayShell) -> Vec<(String, Vec<String>)> {
+        sh.calls()
+            .into_iter()
+            .filter_map(|c| match c {
+                Call::Run { program, args } => Some((program, args)),
+                _ => None,
+            })
+            .collect()
     }
 
-    impl crate::shell::Shell for MockShell {
-        fn run(&self, _program: &str, _args: &[&str]) -> Result<String> {
-            Ok(String::new())
-        }
-        fn run_status(&self, program: &str, args: &[&str]) -> Result<bool> {
-            Ok(*self
-                .status
-                .get(&(program.into(), args.iter().map(|s| s.to_string()).collect()))
-                .unwrap_or(&true))
-        }
-        fn run_tty(&self, _program: &str, _args: &[&str]) -> Result<()> {
-            Ok(())
-        }
-        fn env_var(&self, key: &str) -> Option<String> {
-            self.env.get(key).cloned()
+    fn ask_create(dir: &Path) -> String {
+        format!("Directory {} does not exist. Create it?", dir.display())
+    }
+
+    fn opts(tokens: &[&str], dir: Option<PathBuf>) -> ConnectOptions {
+        ConnectOptions {
+            tokens: tokens.iter().map(|s| s.to_string()).collect(),
+            dir,
+            mkdir: false,
+            projects_root: None,
+            hooks: HooksConfig::default(),
+            env: EnvConfig::default(),
+            refresh_env: RefreshEnvConfig {
+                vars: vec![],
+                broadcast: false,
+            },
+            history: false,
+            multi: false,
+            attach: None,
+            key: false,
+            replay: false,
         }
     }
 
+    fn scratch_dir(tag: &str) -> PathBuf {
+        let d = std::env::temp_dir().join(format!("nitro-connect-{}-{}", tag, std::process::id()));
+        let _ = std::fs::remove_dir_all(&d);
+        d
+    }
+
     #[test]
     fn parse_prefixes_and_path() {
         let p = parse_connect_line(&[
@@ -190,6 +650,57 @@ mod tests {
         assert_eq!(p2.path.as_deref(), Some(std::path::Path::new("/srv/api")));
     }
 
+    #[test]
+    fn parse_server_prefix_only_on_tmux_lines() {
+        let p = parse_connect_line(&["[t]".into(), "work:api".into()]);
+        assert_eq!(p.name, "api");
+        assert_eq!(p.server.as_deref(), Some("work"));
+
+        let p = parse_connect_line(&["".into(), "work:api".into()]);
+        assert_eq!(p.name, "api");
+        assert_eq!(p.server.as_deref(), Some("work"));
+
+        let p = parse_connect_line(&["foo:bar".into()]);
+        assert_eq!(p.name, "foo-bar");
+        assert_eq!(p.server, None);
+    }
+
+    #[test]
+    fn parse_strips_score_annotation() {
+        let p = parse_connect_line(&["[z]".into(), "/src/api".into(), "[12.5]".into()]);
+        assert_eq!(p.name, "api");
+        assert_eq!(p.path.as_deref(), Some(std::path::Path::new("/src/api")));
+        let p = parse_connect_line(&["/src/x".into(), "[draft]".into()]);
+        assert_eq!(
+            p.path.as_deref(),
+            Some(std::path::Path::new("/src/x [draft]"))
+        );
+    }
+
+    #[test]
+    fn parse_pin_lines() {
+        let p = parse_connect_line(&["[p]".into(), "/src/api".into()]);
+        assert_eq!(p.name, "api");
+        assert_eq!(p.path.as_deref(), Some(std::path::Path::new("/src/api")));
+        let p = parse_connect_line(&["".into(), "notes".into()]);
+        assert_eq!(p.name, "notes");
+        assert_eq!(p.path, None);
+    }
+
+    #[test]
+    fn parse_strips_project_type_icons() {
+        let icon = crate::project::ProjectType::Rust.icon();
+        let p = parse_connect_line(&[icon.into(), "/src/api".into()]);
+        assert_eq!(p.name, "api");
+        assert_eq!(p.path.as_deref(), Some(std::path::Path::new("/src/api")));
+        assert_eq!(p.server, None);
+        let p = parse_connect_line(&[format!("{}  work:api", list::ICON_TMUX)]);
+        assert_eq!(
+            (p.name.as_str(), p.server.as_deref()),
+            ("api", Some("work"))
+        );
+    }
+
     #[test]
     fn parse_name_from_basename_when_missing() {
         let p = parse_connect_line(&["/a/b/c".to_string()]);
@@ -197,32 +708,473 @@ mod tests {
         assert_eq!(p.path.as_deref(), Some(std::path::Path::new("/a/b/c")));
     }
 
+    #[test]
+    fn parse_relative_and_home_paths() {
+        let p = parse_connect_line(&["~/code/api".into()]);
+        assert_eq!((p.name.as_str(), p.name_from_path), ("api", true));
+        assert_eq!(p.path.as_deref(), Some(Path::new("~/code/api")));
+        let p = parse_connect_line(&["web".into(), "./src".into()]);
+        assert_eq!((p.name.as_str(), p.name_from_path), ("web", false));
+        let p = parse_connect_line(&["[t]".into(), "~notes".into()]);
+        assert_eq!((p.name.as_str(), p.path), ("~notes", None));
+    }
+
+    #[test]
+    fn connect_expands_home_and_names_after_canonical_dir() -> Result<()> {
+        let home = scratch_dir("home");
+        std::fs::create_dir_all(home.join("code/api-v2"))?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(home.join("code/api-v2"), home.join("code/api"))?;
+        #[cfg(not(unix))]
+        std::fs::create_dir_all(home.join("code/api"))?;
+        let real = home.join("code/api").canonicalize()?;
+        let name = normalize_name(&real.file_name().unwrap().to_string_lossy());
+        let sh = mock()
+            .status("tmux", &["has-session", "-t", &format!("={}", name)], false)
+            .env("HOME", &home.to_string_lossy())
+            .env("TMUX", "1");
+        run_connect(&sh, opts(&["~/code/api"], None))?;
+        std::fs::remove_dir_all(&home)?;
+        assert_eq!(
+            runs(&sh)[0].1,
+            ["new-session", "-ds", &name, "-c", &real.to_string_lossy()]
+        );
+        Ok(())
+    }
+
     #[test]
     fn connect_attaches_if_exists_switch_inside_tmux() -> Result<()> {
         // tmux present, session exists
-        let sh = MockShell::default()
-            .status("tmux", &["has-session", "-t", "web"], true)
-            .with_env("TMUX", "1");
-        run_connect(
-            &sh,
-            ConnectOptions {
-                tokens: vec!["web".into()],
-                dir: None,
-            },
-        )?;
+        let sh = mock()
+            .status("tmux", &["has-session", "-t", "=web"], true)
+            .env("TMUX", "1");
+        run_connect(&sh, opts(&["web"], None))?;
         Ok(())
     }
 
+    #[test]
+    fn connect_rejects_empty_name() {
+        let err = run_connect(&mock(), opts(&["[t]"], None)).unwrap_err();
+        assert_eq!(
+            crate::error::find(&err),
+            Some(&NitroError::EmptySessionName)
+        );
+    }
+
     #[test]
     fn connect_creates_with_dir_then_attaches() -> Result<()> {
-        let sh = MockShell::default().status("tmux", &["has-session", "-t", "web"], false);
-        run_connect(
-            &sh,
-            ConnectOptions {
-                tokens: vec!["web".into()],
-                dir: Some(PathBuf::from("/x")),
-            },
-        )?;
+        let sh = mock().status("tmux", &["has-session", "-t", "=web"], false);
+        run_connect(&sh, opts(&["web"], Some(std::env::temp_dir())))?;
+        Ok(())
+    }
+
+    #[test]
+    fn connect_missing_dir_fails_without_mkdir() {
+        let dir = scratch_dir("nomk");
+        let sh = mock().status("tmux", &["has-session", "-t", "=web"], false);
+        let err = run_connect(&sh, opts(&["web"], Some(dir.clone()))).unwrap_err();
+        assert_eq!(
+            crate::error::find(&err),
+            Some(&NitroError::MissingDirectory(dir.clone()))
+        );
+        assert!(!dir.exists());
+        assert!(runs(&sh).is_empty());
+    }
+
+    #[test]
+    fn connect_creates_missing_dir_with_mkdir_or_confirm() -> Result<()> {
+        let dir = scratch_dir("mk").join("nested");
+        let sh = mock().status("tmux", &["has-session", "-t", "=web"], false);
+        let mut o = opts(&["web"], Some(dir.clone()));
+        o.mkdir = true;
+        run_connect(&sh, o)?;
+        assert!(dir.is_dir());
+        std::fs::remove_dir_all(dir.parent().unwrap())?;
+
+        let dir = scratch_dir("ask");
+        let sh = mock()
+            .status("tmux", &["has-session", "-t", "=web"], false)
+            .confirm_yes(&ask_create(&dir));
+        run_connect(&sh, opts(&["web"], Some(dir.clone())))?;
+        assert!(dir.is_dir());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn connect_confirms_missing_dir_through_socket() -> Result<()> {
+        let dir = scratch_dir("asksock");
+        let sh = mock()
+            .status("tmux", &["-L", "work", "has-session", "-t", "=web"], false)
+            .confirm_yes(&ask_create(&dir));
+        let ssh = tmux::WithSocket::new(&sh, &tmux::Socket::Name("work".into()));
+        run_connect(&ssh, opts(&["web"], Some(dir.clone())))?;
+        assert!(dir.is_dir());
+        assert_eq!(runs(&sh)[0].1[..3], ["-L", "work", "new-session"]);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn server_line_ignores_the_global_socket() -> Result<()> {
+        let sh = mock()
+            .status("tmux", &["-L", "work", "has-session", "-t", "=api"], true)
+            .env("TMUX", "1");
+        let ssh = tmux::WithSocket::new(&sh, &tmux::Socket::Path("/run/main.sock".into()));
+        let mut o = opts(&["t:work:api"], None);
+        o.key = true;
+        run_connect(&ssh, o)?;
+        let calls = runs(&sh);
+        assert!(
+            calls.iter().all(|(_, a)| a[..2] == ["-L", "work"]),
+            "{:?}",
+            calls
+        );
+        assert_eq!(
+            calls.last().unwrap().1,
+            ["-L", "work", "switch-client", "-t", "=api"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn replayed_connect_leaves_directories_alone() -> Result<()> {
+        let dir = scratch_dir("replay");
+        let sh = mock().status("tmux", &["has-session", "-t", "=web"], false);
+        let mut o = opts(&["web"], Some(dir.clone()));
+        o.mkdir = true;
+        o.replay = true;
+        run_connect(&sh, o)?;
+        assert!(!dir.exists());
+        assert_eq!(runs(&sh)[0].1[..2], ["new-session", "-ds"]);
+        Ok(())
+    }
+
+    #[test]
+    fn connect_git_url_clones_into_projects_root() -> Result<()> {
+        let root = scratch_dir("clone");
+        let sh = mock().status("tmux", &["has-session", "-t", "=api"], false);
+        let mut o = opts(&["git@github.com:org/api.git"], None);
+        o.projects_root = Some(root.clone());
+        o.mkdir = true; // the mock does not actually clone
+        run_connect(&sh, o)?;
+        let dest = root.join("api").to_string_lossy().to_string();
+        let calls = runs(&sh);
+        assert_eq!(
+            calls[0],
+            (
+                "git".to_string(),
+                vec![
+                    "clone".to_string(),
+                    "git@github.com:org/api.git".to_string(),
+                    dest.clone()
+                ]
+            )
+        );
+        assert_eq!(calls[1].1, ["new-session", "-ds", "api", "-c", &dest]);
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn projects_root_expands_like_paths() -> Result<()> {
+        let sh = crate::transcript::ReplayShell::default().env("HOME", "/home/u");
+        let mut o = opts(&[], None);
+        assert_eq!(projects_root(&sh, &o)?, PathBuf::from("/home/u/src"));
+        o.projects_root = Some("$HOME/code".into());
+        assert_eq!(projects_root(&sh, &o)?, PathBuf::from("/home/u/code"));
+        Ok(())
+    }
+
+    #[test]
+    fn connect_git_url_reuses_existing_clone() -> Result<()> {
+        let root = scratch_dir("reuse");
+        std::fs::create_dir_all(root.join("web"))?;
+        let sh = mock().status("tmux", &["has-session", "-t", "=web"], false);
+        let mut o = opts(&["https://github.com/org/web"], None);
+        o.projects_root = Some(root.clone());
+        run_connect(&sh, o)?;
+        assert!(runs(&sh).iter().all(|(p, _)| p != "git"));
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn connect_runs_hooks_in_order() -> Result<()> {
+        let sh = mock().status("tmux", &["has-session", "-t", "=web"], false);
+        let mut o = opts(&["web"], Some(std::env::temp_dir()));
+        o.hooks = HooksConfig {
+            pre_create: Some("a".into()),
+            post_create: Some("b".into()),
+            pre_attach: Some("c".into()),
+            post_detach: Some("d".into()),
+        };
+        run_connect(&sh, o)?;
+        let calls = runs(&sh);
+        // hooks run as `env NITRO_HOOK=... sh -c ...`, tmux calls start with the verb
+        let order: Vec<&str> = calls.iter().map(|(_, a)| a[0].as_str()).collect();
+        assert_eq!(
+            order,
+            [
+                "NITRO_HOOK=pre_create",
+                "new-session",
+                "NITRO_HOOK=post_create",
+                "NITRO_HOOK=pre_attach",
+                "NITRO_HOOK=post_detach",
+            ]
+        );
+        assert!(calls[0].1.contains(&"NITRO_SOURCE=dir".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn connect_existing_session_skips_create_hooks() -> Result<()> {
+        let sh = mock()
+            .status("tmux", &["has-session", "-t", "=web"], true)
+            .env("TMUX", "1");
+        let mut o = opts(&["web"], None);
+        o.hooks = HooksConfig {
+            pre_create: Some("a".into()),
+            pre_attach: Some("c".into()),
+            post_detach: Some("d".into()),
+            ..Default::default()
+        };
+        run_connect(&sh, o)?;
+        let calls = runs(&sh);
+        let hooks: Vec<&String> = calls
+            .iter()
+            .filter(|(p, _)| p == "env")
+            .map(|(_, a)| &a[0])
+            .collect();
+        // inside tmux: switch-client returns at once, so no post_detach
+        assert_eq!(hooks, ["NITRO_HOOK=pre_attach"]);
+        Ok(())
+    }
+
+    #[test]
+    fn connect_applies_dotenv_to_new_session() -> Result<()> {
+        let dir = scratch_dir("dotenv");
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(".env"), "API_URL=http://localhost\n")?;
+        let sh = mock().status("tmux", &["has-session", "-t", "=web"], false);
+        let mut o = opts(&["web"], Some(dir.clone()));
+        o.env.dotenv = true;
+        run_connect(&sh, o)?;
+        std::fs::remove_dir_all(&dir)?;
+        let calls = runs(&sh);
+        let create = &calls[0].1;
+        assert_eq!(create[0], "new-session");
+        assert_eq!(create[5..], ["-e", "API_URL=http://localhost"]);
+        Ok(())
+    }
+
+    #[test]
+    fn connect_refreshes_env_only_when_attaching_from_outside() -> Result<()> {
+        let mut o = opts(&["web"], None);
+        o.refresh_env.vars = vec!["SSH_AUTH_SOCK".into()];
+        let sh = mock()
+            .status("tmux", &["has-session", "-t", "=web"], true)
+            .env("SSH_AUTH_SOCK", "/tmp/agent.2");
+        run_connect(&sh, o)?;
+        assert_eq!(
+            runs(&sh)[0].1,
+            [
+                "set-environment",
+                "-t",
+                "web",
+                "SSH_AUTH_SOCK",
+                "/tmp/agent.2"
+            ]
+        );
+
+        let mut o = opts(&["web"], None);
+        o.refresh_env.vars = vec!["SSH_AUTH_SOCK".into()];
+        let sh = mock()
+            .status("tmux", &["has-session", "-t", "=web"], true)
+            .env("TMUX", "1")
+            .env("SSH_AUTH_SOCK", "/tmp/agent.2");
+        run_connect(&sh, o)?;
+        assert!(runs(&sh).iter().all(|(_, a)| a[0] != "set-environment"));
+        Ok(())
+    }
+
+    #[test]
+    fn connect_records_history_with_server() -> Result<()> {
+        let data = scratch_dir("history");
+        let sh = mock()
+            .status("tmux", &["-L", "work", "has-session", "-t", "=api"], true)
+            .env("TMUX", "1")
+            .env("NITRO_DATA_DIR", &data.to_string_lossy());
+        let mut o = opts(&["[t]", "work:api"], None);
+        o.history = true;
+        run_connect(&sh, o)?;
+        let visits = history::load(&sh)?;
+        std::fs::remove_dir_all(&data)?;
+        assert_eq!(visits.len(), 1);
+        assert_eq!(visits[0].server.as_deref(), Some("work"));
+        assert_eq!(visits[0].session, "api");
+        Ok(())
+    }
+
+    #[test]
+    fn connect_lines_split_args_and_newlines() {
+        let t = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            connect_lines(&t(&["[t]", "my", "web"]), false),
+            ["[t] my web"]
+        );
+        let lines = connect_lines(&t(&["[t] api", "[z] /src/web"]), true);
+        assert_eq!(lines, ["[t] api", "[z] /src/web"]);
+        let lines = connect_lines(&t(&["[t] api\n[z] /src/web\n"]), false);
+        assert_eq!(lines.len(), 2);
+        assert!(connect_lines(&[], true).is_empty());
+    }
+
+    #[test]
+    fn connect_many_creates_detached_and_attaches_chosen() -> Result<()> {
+        let web = scratch_dir("many-web");
+        let docs = scratch_dir("many-docs");
+        std::fs::create_dir_all(&web)?;
+        std::fs::create_dir_all(&docs)?;
+        let web_name = normalize_name(&web.file_name().unwrap().to_string_lossy());
+        let docs_name = normalize_name(&docs.file_name().unwrap().to_string_lossy());
+        let sh = mock()
+            .status("tmux", &["has-session", "-t", "=api"], true)
+            .status(
+                "tmux",
+                &["has-session", "-t", &format!("={}", web_name)],
+                false,
+            )
+            .status(
+                "tmux",
+                &["has-session", "-t", &format!("={}", docs_name)],
+                false,
+            )
+            .env("TMUX", "1");
+        let mut o = opts(
+            &[
+                "[t] api",
+                &format!("[z] {}", web.display()),
+                "",
+                &format!("[z] {}", docs.display()),
+            ],
+            None,
+        );
+        o.multi = true;
+        o.attach = Some(docs_name.clone());
+        run_connect(&sh, o)?;
+        std::fs::remove_dir_all(&web)?;
+        std::fs::remove_dir_all(&docs)?;
+        let calls = runs(&sh);
+        let created: Vec<&str> = calls
+            .iter()
+            .filter(|(_, a)| a[0] == "new-session")
+            .map(|(_, a)| a[2].as_str())
+            .collect();
+        assert_eq!(created, [web_name.as_str(), docs_name.as_str()]);
+        let last = &calls.last().unwrap().1;
+        assert_eq!(
+            last[..],
+            ["switch-client", "-t", &format!("={}", docs_name)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn connect_without_terminal_outside_tmux_creates_nothing() {
+        // `nitro list | fzf --multi | nitro connect` from cron or a script
+        let sh = || {
+            mock()
+                .status("tmux", &["has-session", "-t", "=api"], false)
+                .status("tmux", &["has-session", "-t", "=web"], false)
+                .no_terminal()
+        };
+        let mut o = opts(&["api", "web"], Some(std::env::temp_dir()));
+        o.multi = true;
+        let outside = sh();
+        let err = run_connect(&outside, o).unwrap_err();
+        assert_eq!(crate::error::find(&err), Some(&NitroError::NotATerminal));
+        assert!(outside.calls().is_empty());
+
+        // Inside tmux the client switches instead, no terminal needed
+        let sh = sh().env("TMUX", "1");
+        let mut o = opts(&["api", "web"], Some(std::env::temp_dir()));
+        o.multi = true;
+        assert!(run_connect(&sh, o).is_ok());
+        assert_eq!(runs(&sh).len(), 3);
+    }
+
+    #[test]
+    fn connect_many_rejects_unknown_attach_target() {
+        let sh = mock().env("TMUX", "1");
+        let mut o = opts(&["[t] api", "[t] web"], None);
+        o.multi = true;
+        o.attach = Some("docs".into());
+        let err = run_connect(&sh, o).unwrap_err();
+        assert!(err.to_string().contains("not among"));
+        assert!(runs(&sh).is_empty(), "{:?}", runs(&sh));
+    }
+
+    #[test]
+    fn attach_needs_several_lines() {
+        let sh = mock().env("TMUX", "1");
+        let mut o = opts(&["[t] api"], None);
+        o.attach = Some("api".into());
+        let err = run_connect(&sh, o).unwrap_err();
+        assert!(err.to_string().contains("only one"));
+        assert!(sh.calls().is_empty());
+    }
+
+    #[test]
+    fn key_lines_resolve_exactly() -> Result<()> {
+        let p = parse_key_line("t::my session\t[t] my session")?;
+        assert_eq!(
+            (p.name.as_str(), p.path, p.server),
+            ("my session", None, None)
+        );
+        let p = parse_key_line("t:work:api")?;
+        assert_eq!(p.server.as_deref(), Some("work"));
+        let p = parse_key_line("z:/src/a  b/c d\t[z] /src/a  b/c d")?;
+        assert_eq!(p.name, "c-d");
+        assert_eq!(p.path.as_deref(), Some(Path::new("/src/a  b/c d")));
+        let p = parse_key_line("p:notes")?;
+        assert_eq!((p.name.as_str(), p.path), ("notes", None));
+        assert!(parse_key_line("[t] api").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn connect_by_key_keeps_exact_session_name() -> Result<()> {
+        let sh = mock()
+            .status("tmux", &["has-session", "-t", "=my session"], true)
+            .env("TMUX", "1");
+        let mut o = opts(&["t::my session\t[t] my session"], None);
+        o.key = true;
+        run_connect(&sh, o)?;
+        assert_eq!(
+            runs(&sh).last().unwrap().1,
+            ["switch-client", "-t", "=my session"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn connect_by_key_ignores_sessions_sharing_a_prefix() -> Result<()> {
+        // Only "api-v2" runs: tmux would match a bare "api" against it, and
+        // the mock answers yes to every target it was not told about
+        let sh = mock()
+            .status("tmux", &["has-session", "-t", "=api"], false)
+            .env("TMUX", "1");
+        let mut o = opts(&["t::api\t[t] api"], None);
+        o.key = true;
+        run_connect(&sh, o)?;
+        let calls = runs(&sh);
+        assert!(
+            calls
+                .iter()
+                .any(|(_, a)| a.join(" ").starts_with("new-session -ds api "))
+        );
+        assert_eq!(calls.last().unwrap().1, ["switch-client", "-t", "=api"]);
         Ok(())
     }
 }
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Failures callers may want to tell apart, each mapped to a stable exit code.
+///
+/// Anything not covered here surfaces as a plain `anyhow` error and exits
+/// with code 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum NitroError {
+    /// A required program (tmux, zoxide) could not be spawned.
+    MissingProgram(String),
+    /// tmux is installed but no server is running.
+    NoServer,
+    /// The connect line resolved to an empty session name.
+    EmptySessionName,
+    /// `tmux attach` needs a terminal and stdin is not one.
+    NotATerminal,
+    /// The directory for a new session does not exist and was not created.
+    MissingDirectory(PathBuf),
+    /// A command ran but exited unsuccessfully.
+    CommandFailed {
+        /// The program that was run
+        program: String,
+        /// Its arguments
+        args: Vec<String>,
+        /// Its stderr, or how it failed
+        detail: String,
+    },
+}
+
+/// Generic failure (anything without a dedicated code). Code 2 is left to
+/// clap for usage errors.
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_MISSING_PROGRAM: u8 = 3;
+pub const EXIT_NO_SERVER: u8 = 4;
+pub const EXIT_EMPTY_NAME: u8 = 5;
+pub const EXIT_NOT_A_TERMINAL: u8 = 6;
+pub const EXIT_COMMAND_FAILED: u8 = 7;
+pub const EXIT_MISSING_DIRECTORY: u8 = 8;
+
+impl NitroError {
+    /// A [`NitroError::CommandFailed`] for `program args`.
+    pub fn command_failed(program: &str, args: &[&str], detail: impl Into<String>) -> Self {
+        NitroError::CommandFailed {
+            program: program.to_string(),
+            args: args.iter().map(|s| s.to_string()).collect(),
+            detail: detail.into(),
+        }
+    }
+
+    /// The process exit code `nitro` uses for this error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            NitroError::MissingProgram(_) => EXIT_MISSING_PROGRAM,
+            NitroError::NoServer => EXIT_NO_SERVER,
+            NitroError::EmptySessionName => EXIT_EMPTY_NAME,
+            NitroError::NotATerminal => EXIT_NOT_A_TERMINAL,
+            NitroError::CommandFailed { .. } => EXIT_COMMAND_FAILED,
+            NitroError::MissingDirectory(_) => EXIT_MISSING_DIRECTORY,
+        }
+    }
+
+    /// Short, actionable suggestion printed after the error message.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            NitroError::MissingProgram(p) => {
+                Some(format!("install {p} and make sure it is on PATH"))
+            }
+            NitroError::NoServer => {
+                Some("start one with `tmux new-session` or `nitro connect <name>`".into())
+            }
+            NitroError::EmptySessionName => {
+                Some("pass a session name or a path, e.g. `nitro connect api`".into())
+            }
+            NitroError::NotATerminal => Some(
+                "run nitro from an interactive terminal, or from inside tmux to switch clients"
+                    .into(),
+            ),
+            NitroError::CommandFailed { .. } => None,
+            NitroError::MissingDirectory(_) => {
+                Some("pass --mkdir to create it, or --dir to start elsewhere".into())
+            }
+        }
+    }
+}
+
+impl fmt::Display for NitroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NitroError::MissingProgram(p) => write!(f, "{p} not found on PATH"),
+            NitroError::NoServer => write!(f, "no tmux server running"),
+            NitroError::EmptySessionName => write!(f, "empty session name"),
+            NitroError::NotATerminal => write!(f, "cannot attach: not a terminal"),
+            NitroError::CommandFailed {
+                program,
+                args,
+                detail,
+            } => write!(f, "{program} {args:?} failed: {detail}"),
+            NitroError::MissingDirectory(p) => {
+                write!(f, "directory {} does not exist", p.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for NitroError {}
+
+/// Find the first `NitroError` in an error chain, if any.
+pub fn find(err: &anyhow::Error) -> Option<&NitroError> {
+    err.chain().find_map(|e| e.downcast_ref::<NitroError>())
+}
+
+/// Exit code for an arbitrary error: the typed code when known, else 1.
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    find(err).map(NitroError::exit_code).unwrap_or(EXIT_FAILURE)
+}
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Color the `error`/`warning`/`hint` labels of messages printed from now
+/// on. Off unless the binary decides stderr takes colors.
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+fn label(code: &str, text: &str) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        format!("\u{001b}[{}m{}\u{001b}[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn print_hint(err: &anyhow::Error) {
+    if let Some(hint) = find(err).and_then(NitroError::hint) {
+        eprintln!("{} {hint}", label("36", "hint:"));
+    }
+}
+
+/// Print an error and its hint (if any) to stderr.
+pub fn report(err: &anyhow::Error) {
+    eprintln!("{} {err:#}", label("1;31", "nitro:"));
+    print_hint(err);
+}
+
+/// Print a non-fatal error (and its hint) to stderr.
+pub fn warn(err: &anyhow::Error) {
+    eprintln!("nitro: {} {err:#}", label("33", "warning:"));
+    print_hint(err);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let errs = [
+            NitroError::MissingProgram("tmux".into()),
+            NitroError::NoServer,
+            NitroError::EmptySessionName,
+            NitroError::NotATerminal,
+            NitroError::command_failed("tmux", &["attach"], "boom"),
+            NitroError::MissingDirectory("/x".into()),
+        ];
+        let mut codes: Vec<u8> = errs.iter().map(NitroError::exit_code).collect();
+        codes.push(EXIT_FAILURE);
+        codes.push(2); // clap usage errors
+        let n = codes.len();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), n);
+    }
+
+    #[test]
+    fn exit_code_found_through_context() {
+        let err = anyhow::Error::from(NitroError::NoServer).context("listing sessions");
+        assert_eq!(exit_code(&err), EXIT_NO_SERVER);
+        let res: anyhow::Result<()> = Err(NitroError::EmptySessionName.into());
+        let err = res.context("connect").unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_EMPTY_NAME);
+        assert_eq!(exit_code(&anyhow::anyhow!("other")), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn missing_program_message_and_hint() {
+        let e = NitroError::MissingProgram("tmux".into());
+        assert_eq!(e.to_string(), "tmux not found on PATH");
+        assert!(e.hint().unwrap().contains("install tmux"));
+    }
+}
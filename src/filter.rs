@@ -0,0 +1,165 @@
+use crate::config::{FilterConfig, RuleConfig};
+use crate::paths;
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// A compiled include/exclude pattern.
+///
+/// Plain patterns are globs matched against the whole text: `*` and `?` stop
+/// at `/`, `**` crosses directories, and a leading `~`, `~user` or `$VAR` is
+/// expanded as in connect paths.
+/// Patterns prefixed with `re:` are regular expressions searched anywhere.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn parse<S: Shell>(sh: &S, pattern: &str) -> Result<Pattern> {
+        let re = match pattern.strip_prefix("re:") {
+            Some(re) => Regex::new(re),
+            // Other patterns stay relative: `_popup*` is a session name
+            None if pattern.starts_with(['~', '$']) => {
+                let expanded = paths::expand(sh, pattern)
+                    .with_context(|| format!("invalid pattern {:?}", pattern))?;
+                Regex::new(&glob_to_regex(&expanded.to_string_lossy()))
+            }
+            None => Regex::new(&glob_to_regex(pattern)),
+        };
+        let re = re.with_context(|| format!("invalid pattern {:?}", pattern))?;
+        Ok(Pattern(re))
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut out = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    // "**/" also matches no directory at all
+                    chars.next();
+                    out.push_str("(?:.*/)?");
+                } else {
+                    out.push_str(".*");
+                }
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    out.push('$');
+    out
+}
+
+/// Include/exclude rules for one source. An entry is kept when it matches
+/// some include (or there are none) and no exclude.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Rules {
+    pub fn from_config<S: Shell>(
+        sh: &S,
+        cfg: &RuleConfig,
+        extra_exclude: &[String],
+    ) -> Result<Rules> {
+        let parse_all = |v: &[String]| {
+            v.iter()
+                .map(|p| Pattern::parse(sh, p))
+                .collect::<Result<_>>()
+        };
+        let mut exclude: Vec<Pattern> = parse_all(&cfg.exclude)?;
+        exclude.extend(parse_all(extra_exclude)?);
+        Ok(Rules {
+            include: parse_all(&cfg.include)?,
+            exclude,
+        })
+    }
+
+    pub fn allows(&self, text: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(text)))
+            && !self.exclude.iter().any(|p| p.matches(text))
+    }
+}
+
+/// Rules per list source: tmux session names and zoxide paths.
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    pub tmux: Rules,
+    pub zoxide: Rules,
+}
+
+impl Filters {
+    /// Config rules per source, plus `--exclude` patterns applied to every source.
+    pub fn new<S: Shell>(sh: &S, cfg: &FilterConfig, exclude: &[String]) -> Result<Filters> {
+        Ok(Filters {
+            tmux: Rules::from_config(sh, &cfg.tmux, exclude)?,
+            zoxide: Rules::from_config(sh, &cfg.zoxide, exclude)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::ReplayShell;
+
+    fn sh() -> ReplayShell {
+        ReplayShell::default().env("HOME", "/home/u")
+    }
+
+    fn m(pattern: &str, text: &str) -> bool {
+        Pattern::parse(&sh(), pattern).unwrap().matches(text)
+    }
+
+    #[test]
+    fn glob_semantics() {
+        assert!(m("/tmp/*", "/tmp/x"));
+        assert!(!m("/tmp/*", "/tmp/x/y"));
+        assert!(m("/tmp/**", "/tmp/x/y"));
+        assert!(m("**/node_modules", "/a/b/node_modules"));
+        assert!(m("**/node_modules/**", "/a/node_modules/pkg"));
+        assert!(!m("**/node_modules", "/a/node_modules_old"));
+        assert!(m("_popup*", "_popup-3"));
+        assert!(!m("_popup*", "work_popup"));
+        assert!(m("v?", "v1"));
+        assert!(m("a.b", "a.b"));
+        assert!(!m("a.b", "axb"));
+        assert!(m("~", "/home/u"));
+        assert!(!m("~", "/home/u/x"));
+        assert!(m("$HOME/tmp/*", "/home/u/tmp/x"));
+        assert!(Pattern::parse(&sh(), "$NITRO_UNSET/**").is_err());
+    }
+
+    #[test]
+    fn regex_patterns_search_anywhere() {
+        assert!(m("re:vendor", "/src/app/vendor/lib"));
+        assert!(m("re:^_", "_scratch"));
+        assert!(!m("re:^_", "work"));
+        assert!(Pattern::parse(&sh(), "re:(").is_err());
+    }
+
+    #[test]
+    fn rules_include_then_exclude() -> Result<()> {
+        let cfg = RuleConfig {
+            include: vec!["/src/**".into()],
+            exclude: vec!["**/vendor/**".into()],
+        };
+        let rules = Rules::from_config(&sh(), &cfg, &["**/tmp".into()])?;
+        assert!(rules.allows("/src/api"));
+        assert!(!rules.allows("/home/u/api"));
+        assert!(!rules.allows("/src/api/vendor/x"));
+        assert!(!rules.allows("/src/tmp"));
+        assert!(Rules::default().allows("anything"));
+        Ok(())
+    }
+}
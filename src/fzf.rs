@@ -0,0 +1,383 @@
+use crate::connect::{self, ConnectOptions, normalize_name};
+use crate::git;
+use crate::list::{self, ListOptions, Source};
+use crate::project::ProjectType;
+use crate::shell::Shell;
+use crate::tmux;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Prompts name the sources being listed, so reloads after a kill or
+/// rename keep them: fzf passes the prompt on as `$FZF_PROMPT`.
+const PROMPT_ALL: &str = "nitro> ";
+const PROMPT_TMUX: &str = "tmux> ";
+const PROMPT_ZOXIDE: &str = "zoxide> ";
+
+const HEADER: &str = "enter connect · ctrl-x kill · ctrl-r rename to query · ctrl-t tmux · ctrl-z zoxide · ctrl-a all";
+
+/// Directory entries shown in a preview.
+const PREVIEW_ENTRIES: usize = 100;
+
+#[non_exhaustive]
+pub struct FzfOptions {
+    /// Runs nitro again for the bindings: the binary with the server and
+    /// color flags, then `fzf` and its list flags
+    pub callback: Vec<String>,
+    /// Color the list and pass `--ansi`
+    pub color: bool,
+    /// Extra fzf arguments, from the config
+    pub fzf_args: Vec<String>,
+}
+
+impl FzfOptions {
+    pub fn new(callback: Vec<String>, color: bool) -> FzfOptions {
+        FzfOptions {
+            callback,
+            color,
+            fzf_args: Vec::new(),
+        }
+    }
+}
+
+fn shell_quote(s: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+/// A shell command running `nitro ... fzf ... --<action>`; `fields` are
+/// fzf placeholders such as `{1}`, which fzf quotes itself.
+fn callback(opts: &FzfOptions, action: &str, fields: &[&str]) -> String {
+    let words: Vec<String> = opts
+        .callback
+        .iter()
+        .map(|w| shell_quote(w))
+        .chain(std::iter::once(action.to_string()))
+        .chain(fields.iter().map(|f| f.to_string()))
+        .collect();
+    words.join(" ")
+}
+
+/// The fzf command line: tab-delimited lines whose first field (the key)
+/// is hidden, a preview, and the key bindings.
+pub fn fzf_args(opts: &FzfOptions) -> Vec<String> {
+    let reload = format!("reload({})", callback(opts, "--list", &[]));
+    let switch = |prompt: &str| format!("change-prompt({})+{}", prompt, reload);
+    let mut args: Vec<String> = vec![
+        "--multi".into(),
+        "--delimiter=\t".into(),
+        "--with-nth=2..".into(),
+        "--tiebreak=index".into(),
+        format!("--prompt={}", PROMPT_ALL),
+        format!("--header={}", HEADER),
+        format!("--preview={}", callback(opts, "--preview", &["{1}"])),
+        format!(
+            "--bind=ctrl-x:execute-silent({})+{}",
+            callback(opts, "--kill", &["{1}"]),
+            reload
+        ),
+        format!(
+            "--bind=ctrl-r:execute-silent({})+clear-query+{}",
+            callback(opts, "--rename", &["{1}", "{q}"]),
+            reload
+        ),
+        format!("--bind=ctrl-t:{}", switch(PROMPT_TMUX)),
+        format!("--bind=ctrl-z:{}", switch(PROMPT_ZOXIDE)),
+        format!("--bind=ctrl-a:{}", switch(PROMPT_ALL)),
+    ];
+    if opts.color {
+        args.push("--ansi".into());
+    }
+    args.extend(opts.fzf_args.iter().cloned());
+    args
+}
+
+/// Narrow `list` to the sources `$FZF_PROMPT` names; any other prompt keeps
+/// the sources it was given.
+pub fn apply_prompt<S: Shell>(sh: &S, list: &mut ListOptions) {
+    let only = |list: &mut ListOptions, tmux: bool| {
+        list.include_pins = false;
+        list.include_tmux = tmux;
+        list.include_zox = !tmux;
+    };
+    match sh.env_var("FZF_PROMPT").as_deref() {
+        Some(PROMPT_TMUX) => only(list, true),
+        Some(PROMPT_ZOXIDE) => only(list, false),
+        _ => {}
+    }
+}
+
+/// Pick entries from `list` (rendered with `--delimited`) in fzf and
+/// connect to them. Cancelling fzf connects to nothing.
+pub fn run_fzf<S: Shell>(
+    sh: &S,
+    list: &ListOptions,
+    opts: &FzfOptions,
+    mut connect: ConnectOptions,
+) -> Result<()> {
+    let input = list::build_list_lines(sh, list)?.join("\n");
+    let args = fzf_args(opts);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let picked = sh.run_picker("fzf", &args, &input)?;
+    let lines: Vec<String> = picked
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(str::to_string)
+        .collect();
+    if lines.is_empty() {
+        return Ok(());
+    }
+    connect.tokens = lines;
+    connect.multi = true;
+    connect.key = true;
+    connect::run_connect(sh, connect)
+}
+
+/// Print what the entry with `key` holds: the screen of a running
+/// session, or the project type, branch and files of a directory.
+pub fn run_preview<S: Shell>(sh: &S, key: &str) -> Result<()> {
+    let line = connect::parse_key_line(key)?;
+    let server = line.server.map(tmux::Socket::Name).unwrap_or_default();
+    let ssh = tmux::WithSocket::new(sh, &server);
+    if tmux::has_session(&ssh, &line.name).unwrap_or(false) {
+        print!("{}", tmux::capture_pane(&ssh, &line.name)?);
+        return Ok(());
+    }
+    let Some(dir) = line.path else {
+        println!("no session {}", line.name);
+        return Ok(());
+    };
+    print!("{}", describe_dir(sh, &dir));
+    Ok(())
+}
+
+/// A directory's path, project type and branch, then its entries with
+/// subdirectories marked by a trailing `/`.
+fn describe_dir<S: Shell>(sh: &S, dir: &Path) -> String {
+    let mut head = vec![dir.display().to_string()];
+    head.extend(ProjectType::detect(dir).map(|t| t.name().to_string()));
+    head.extend(git::current_branch(sh, dir).map(|b| format!("({})", b)));
+    let mut out = head.join("  ") + "\n\n";
+    let Ok(read) = std::fs::read_dir(dir) else {
+        out.push_str("(missing)\n");
+        return out;
+    };
+    let mut names: Vec<String> = read
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            if e.path().is_dir() { name + "/" } else { name }
+        })
+        .collect();
+    names.sort();
+    let extra = names.len().saturating_sub(PREVIEW_ENTRIES);
+    for name in names.into_iter().take(PREVIEW_ENTRIES) {
+        out.push_str(&name);
+        out.push('\n');
+    }
+    if extra > 0 {
+        out.push_str(&format!("… {} more\n", extra));
+    }
+    out
+}
+
+/// The tmux server and session an entry with `key` names: tmux entries
+/// and session pins. Directory entries name no session, even when one with
+/// their basename happens to run.
+fn key_session(key: &str) -> Result<Option<(Option<String>, String)>> {
+    let key = list::parse_key(key).with_context(|| format!("invalid list key {:?}", key))?;
+    Ok(match key.source {
+        Source::Tmux => Some((key.server, key.id)),
+        Source::Pin if !key.id.starts_with('/') => Some((None, normalize_name(&key.id))),
+        _ => None,
+    })
+}
+
+/// Kill the session of the entry with `key`, if it runs.
+pub fn run_kill<S: Shell>(sh: &S, key: &str) -> Result<()> {
+    let Some((server, name)) = key_session(key)? else {
+        return Ok(());
+    };
+    let server = server.map(tmux::Socket::Name).unwrap_or_default();
+    let ssh = tmux::WithSocket::new(sh, &server);
+    if tmux::has_session(&ssh, &name)? {
+        tmux::kill_session(&ssh, &name)?;
+    }
+    Ok(())
+}
+
+/// Rename the session of the entry with `key` to `new_name` (normalized
+/// like other session names). Nothing happens for an empty name or an
+/// entry without a running session.
+pub fn run_rename<S: Shell>(sh: &S, key: &str, new_name: &str) -> Result<()> {
+    let Some((server, name)) = key_session(key)? else {
+        return Ok(());
+    };
+    let new_name = normalize_name(new_name);
+    if new_name.is_empty() || new_name == name {
+        return Ok(());
+    }
+    let server = server.map(tmux::Socket::Name).unwrap_or_default();
+    let ssh = tmux::WithSocket::new(sh, &server);
+    if tmux::has_session(&ssh, &name)? {
+        tmux::rename_session(&ssh, &name, &new_name)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::transcript::{Call, ReplayShell};
+
+    fn opts() -> FzfOptions {
+        FzfOptions {
+            callback: vec![
+                "/opt/my nitro".into(),
+                "-L".into(),
+                "work".into(),
+                "fzf".into(),
+            ],
+            color: false,
+            fzf_args: vec!["--height=50%".into()],
+        }
+    }
+
+    #[test]
+    fn bindings_call_back_into_nitro() {
+        let args = fzf_args(&opts());
+        let nitro = "'/opt/my nitro' -L work fzf";
+        assert!(args.contains(&format!("--preview={} --preview {{1}}", nitro)));
+        assert!(args.contains(&format!(
+            "--bind=ctrl-x:execute-silent({0} --kill {{1}})+reload({0} --list)",
+            nitro
+        )));
+        assert!(args.contains(&format!(
+            "--bind=ctrl-r:execute-silent({0} --rename {{1}} {{q}})+clear-query+reload({0} --list)",
+            nitro
+        )));
+        assert!(args.contains(&format!(
+            "--bind=ctrl-t:change-prompt(tmux> )+reload({} --list)",
+            nitro
+        )));
+        assert!(args.contains(&"--with-nth=2..".to_string()));
+        assert!(!args.contains(&"--ansi".to_string()));
+        assert_eq!(args.last().map(String::as_str), Some("--height=50%"));
+    }
+
+    #[test]
+    fn prompt_picks_the_sources() {
+        let mut list = ListOptions::default();
+        apply_prompt(&ReplayShell::default(), &mut list);
+        assert!(list.include_pins && list.include_tmux && list.include_zox);
+
+        let sh = ReplayShell::default().env("FZF_PROMPT", PROMPT_ZOXIDE);
+        apply_prompt(&sh, &mut list);
+        assert!(!list.include_pins && !list.include_tmux && list.include_zox);
+        let sh = ReplayShell::default().env("FZF_PROMPT", PROMPT_TMUX);
+        apply_prompt(&sh, &mut list);
+        assert!(!list.include_pins && list.include_tmux && !list.include_zox);
+    }
+
+    #[test]
+    fn picked_lines_connect_by_key() -> Result<()> {
+        let args = fzf_args(&opts());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", "#S"], "api\n")
+            .output("fzf", &args, "t::api\t[t] api\n")
+            .status("tmux", &["has-session", "-t", "=api"], true)
+            .tty("tmux", &["attach", "-t", "=api"]);
+        let list = ListOptions {
+            include_pins: false,
+            include_zox: false,
+            delimited: true,
+            ..Default::default()
+        };
+        let connect = ConnectOptions::new(vec![], &Config::default());
+        run_fzf(&sh, &list, &opts(), connect)?;
+        assert!(sh.unused().is_empty(), "{:?}", sh.unused());
+        Ok(())
+    }
+
+    #[test]
+    fn cancelled_fzf_connects_nothing() -> Result<()> {
+        let args = fzf_args(&opts());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let list = ListOptions {
+            include_pins: false,
+            include_zox: false,
+            ..Default::default()
+        };
+        // Anything after the picker would have no answer
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", "#S"], "api\n")
+            .output("fzf", &args, "");
+        let connect = ConnectOptions::new(vec![], &Config::default());
+        run_fzf(&sh, &list, &opts(), connect)?;
+        Ok(())
+    }
+
+    #[test]
+    fn kill_and_rename_only_touch_running_sessions() -> Result<()> {
+        let sh = ReplayShell::default()
+            .status("tmux", &["has-session", "-t", "=api"], true)
+            .output("tmux", &["kill-session", "-t", "=api"], "")
+            .status("tmux", &["has-session", "-t", "=api"], true)
+            .output("tmux", &["rename-session", "-t", "=api", "new-api"], "")
+            .status("tmux", &["has-session", "-t", "=notes"], false)
+            .status("tmux", &["-L", "work", "has-session", "-t", "=api"], false);
+        run_kill(&sh, "t::api")?;
+        run_rename(&sh, "t::api", "new api")?;
+        run_rename(&sh, "t::api", "")?;
+        run_rename(&sh, "p:notes", "n")?;
+        run_kill(&sh, "t:work:api")?; // not running on "work"
+        assert!(sh.unused().is_empty(), "{:?}", sh.unused());
+        Ok(())
+    }
+
+    #[test]
+    fn kill_and_rename_leave_directory_entries_alone() -> Result<()> {
+        let sh = ReplayShell::default()
+            .status("tmux", &["has-session", "-t", "=api"], true)
+            .status("tmux", &["has-session", "-t", "=notes"], true)
+            .output("tmux", &["kill-session", "-t", "=notes"], "");
+        // A session named after the basename runs, but is not this entry's
+        run_kill(&sh, "z:/src/api")?;
+        run_kill(&sh, "p:/src/api")?;
+        run_rename(&sh, "z:/src/api", "web")?;
+        run_kill(&sh, "p:notes")?;
+        let api = Call::RunStatus {
+            program: "tmux".into(),
+            args: vec!["has-session".into(), "-t".into(), "=api".into()],
+        };
+        assert_eq!(sh.unused(), [api]);
+        assert!(run_kill(&sh, "x:api").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn directory_preview_lists_entries() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("nitro-fzf-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src"))?;
+        std::fs::write(dir.join("Cargo.toml"), "")?;
+        let path = dir.to_string_lossy();
+        let sh = ReplayShell::default().output(
+            "git",
+            &["-C", &path, "rev-parse", "--abbrev-ref", "HEAD"],
+            "main\n",
+        );
+        let text = describe_dir(&sh, &dir);
+        assert_eq!(
+            text,
+            format!("{}  rust  (main)\n\nCargo.toml\nsrc/\n", dir.display())
+        );
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
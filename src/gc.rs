@@ -0,0 +1,225 @@
+use crate::config::GcConfig;
+use crate::filter::Pattern;
+use crate::shell::Shell;
+use crate::tmux::{self, SessionInfo};
+use anyhow::{Context, Result};
+
+const DEFAULT_IDLE: u64 = 7 * 24 * 60 * 60;
+
+#[non_exhaustive]
+pub struct GcOptions {
+    /// Seconds a detached session may sit unused
+    pub idle: u64,
+    /// Session names that are never killed
+    pub exclude: Vec<Pattern>,
+    /// Keep at most this many sessions in total
+    pub max_sessions: Option<usize>,
+    pub dry_run: bool,
+}
+
+impl GcOptions {
+    /// Config defaults overridden by command-line values.
+    pub fn new<S: Shell>(
+        sh: &S,
+        cfg: &GcConfig,
+        idle: Option<&str>,
+        exclude: &[String],
+        max_sessions: Option<usize>,
+        dry_run: bool,
+    ) -> Result<GcOptions> {
+        let idle = match idle.or(cfg.idle.as_deref()) {
+            Some(s) => parse_duration(s)?,
+            None => DEFAULT_IDLE,
+        };
+        let exclude = cfg
+            .exclude
+            .iter()
+            .chain(exclude)
+            .map(|p| Pattern::parse(sh, p))
+            .collect::<Result<_>>()?;
+        Ok(GcOptions {
+            idle,
+            exclude,
+            max_sessions: max_sessions.or(cfg.max_sessions),
+            dry_run,
+        })
+    }
+}
+
+/// Parse `90`, `30s`, `15m`, `12h`, `7d` or `2w` into seconds.
+pub fn parse_duration(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (num, unit) = text.split_at(split);
+    let n: u64 = num
+        .parse()
+        .with_context(|| format!("invalid duration {:?}", text))?;
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => anyhow::bail!("invalid duration {:?} (use s, m, h, d or w)", text),
+    };
+    n.checked_mul(scale)
+        .with_context(|| format!("duration {:?} is too large", text))
+}
+
+/// Short age such as `45s`, `12m`, `3h` or `9d`.
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h", s / (60 * 60)),
+        s => format!("{}d", s / (24 * 60 * 60)),
+    }
+}
+
+/// Sessions to kill, least recently used first: detached, non-excluded
+/// sessions idle longer than `opts.idle`, then more of them (oldest first)
+/// while the total exceeds `opts.max_sessions`. Attached sessions are never
+/// chosen, so the cap may not be reachable.
+pub fn select<'a>(sessions: &'a [SessionInfo], opts: &GcOptions, now: u64) -> Vec<&'a SessionInfo> {
+    let mut candidates: Vec<&SessionInfo> = sessions
+        .iter()
+        .filter(|s| s.attached == 0)
+        .filter(|s| !opts.exclude.iter().any(|p| p.matches(&s.name)))
+        .collect();
+    candidates.sort_by_key(|s| s.last_used());
+    let idle = candidates
+        .iter()
+        .take_while(|s| now.saturating_sub(s.last_used()) > opts.idle)
+        .count();
+    let over_cap = opts
+        .max_sessions
+        .map(|max| sessions.len().saturating_sub(max))
+        .unwrap_or(0);
+    candidates.truncate(idle.max(over_cap));
+    candidates
+}
+
+pub fn run_gc<S: Shell>(sh: &S, opts: &GcOptions, now: u64) -> Result<()> {
+    let sessions = tmux::list_session_info(sh)?;
+    for s in select(&sessions, opts, now) {
+        if !opts.dry_run {
+            tmux::kill_session(sh, &s.name)?;
+        }
+        println!(
+            "{}\tidle {}",
+            s.name,
+            format_age(now.saturating_sub(s.last_used()))
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::ReplayShell;
+
+    const DAY: u64 = 24 * 60 * 60;
+    const NOW: u64 = 100 * DAY;
+
+    fn info(name: &str, idle_days: u64, attached: u32) -> SessionInfo {
+        SessionInfo {
+            name: name.into(),
+            path: format!("/src/{}", name),
+            windows: 1,
+            attached,
+            activity: NOW - idle_days * DAY,
+            last_attached: 0,
+        }
+    }
+
+    fn opts(idle: &str, exclude: &[&str], max: Option<usize>) -> GcOptions {
+        let exclude: Vec<String> = exclude.iter().map(|s| s.to_string()).collect();
+        GcOptions::new(
+            &ReplayShell::default(),
+            &GcConfig::default(),
+            Some(idle),
+            &exclude,
+            max,
+            false,
+        )
+        .unwrap()
+    }
+
+    fn names(v: Vec<&SessionInfo>) -> Vec<&str> {
+        v.into_iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn durations() -> Result<()> {
+        assert_eq!(parse_duration("90")?, 90);
+        assert_eq!(parse_duration("15m")?, 900);
+        assert_eq!(parse_duration("7d")?, 7 * DAY);
+        assert_eq!(parse_duration("2w")?, 14 * DAY);
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("18446744073709551615w").is_err());
+        assert_eq!(format_age(30), "30s");
+        assert_eq!(format_age(3 * 3600), "3h");
+        assert_eq!(format_age(9 * DAY), "9d");
+        Ok(())
+    }
+
+    #[test]
+    fn selects_idle_detached_unexcluded() {
+        let sessions = vec![
+            info("old", 30, 0),
+            info("fresh", 1, 0),
+            info("watched", 30, 1),
+            info("_keep", 30, 0),
+        ];
+        let picked = select(&sessions, &opts("7d", &["_*"], None), NOW);
+        assert_eq!(names(picked), ["old"]);
+    }
+
+    #[test]
+    fn last_attach_counts_as_use() {
+        let mut s = info("api", 30, 0);
+        s.last_attached = NOW - DAY;
+        assert!(select(&[s], &opts("7d", &[], None), NOW).is_empty());
+    }
+
+    #[test]
+    fn cap_evicts_least_recently_used() {
+        let sessions = vec![
+            info("a", 3, 0),
+            info("b", 1, 0),
+            info("c", 2, 0),
+            info("d", 5, 1),
+        ];
+        let picked = select(&sessions, &opts("30d", &[], Some(2)), NOW);
+        assert_eq!(names(picked), ["a", "c"]);
+        // idle sessions go even when under the cap
+        let picked = select(&sessions, &opts("2d", &[], Some(10)), NOW);
+        assert_eq!(names(picked), ["a"]);
+    }
+
+    #[test]
+    fn run_gc_kills_unless_dry_run() -> Result<()> {
+        let fmt = "#{session_name}\t#{session_path}\t#{session_windows}\t#{session_attached}\t#{session_activity}\t#{session_last_attached}";
+        let listing = format!(
+            "old\t/src/old\t2\t0\t{}\t0\nnew\t/src/new\t1\t0\t{}\t0\n",
+            NOW - 30 * DAY,
+            NOW
+        );
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", fmt], &listing)
+            .output("tmux", &["list-sessions", "-F", fmt], &listing)
+            .output("tmux", &["kill-session", "-t", "=old"], "");
+        let mut o = opts("7d", &[], None);
+        o.dry_run = true;
+        run_gc(&sh, &o, NOW)?;
+        assert_eq!(sh.unused().len(), 2);
+        o.dry_run = false;
+        run_gc(&sh, &o, NOW)?;
+        assert!(sh.unused().is_empty());
+        Ok(())
+    }
+}
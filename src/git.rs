@@ -0,0 +1,84 @@
+use crate::shell::Shell;
+use anyhow::Result;
+use std::path::Path;
+
+const URL_PREFIXES: &[&str] = &["https://", "http://", "ssh://", "git://", "file://", "git@"];
+
+pub fn is_git_url(s: &str) -> bool {
+    URL_PREFIXES.iter().any(|p| s.starts_with(p)) && !s.contains(char::is_whitespace)
+}
+
+/// Repository name from a clone URL: last path component without `.git`.
+/// `git@host:org/repo.git` and `https://host/org/repo/` both yield `repo`.
+/// `.` and `..` are not names: they would point the checkout at the projects
+/// root or its parent.
+pub fn repo_name(url: &str) -> Option<String> {
+    let trimmed = url.trim_end_matches('/');
+    let last = trimmed.rsplit(['/', ':']).next()?;
+    let name = last.strip_suffix(".git").unwrap_or(last);
+    if matches!(name, "" | "." | "..") {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Clone in the foreground, so progress shows and credential prompts can be
+/// answered.
+pub fn clone<S: Shell>(sh: &S, url: &str, dest: &Path) -> Result<()> {
+    sh.run_interactive("git", &["clone", url, &dest.to_string_lossy()])
+}
+
+/// Branch checked out in `dir`, or the short commit id when detached.
+/// `None` outside a repository.
+pub fn current_branch<S: Shell>(sh: &S, dir: &Path) -> Option<String> {
+    let dir = dir.to_string_lossy();
+    let branch = sh
+        .run("git", &["-C", &dir, "rev-parse", "--abbrev-ref", "HEAD"])
+        .ok()?;
+    let branch = branch.trim();
+    if branch != "HEAD" {
+        return Some(branch.to_string()).filter(|b| !b.is_empty());
+    }
+    let commit = sh
+        .run("git", &["-C", &dir, "rev-parse", "--short", "HEAD"])
+        .ok()?;
+    Some(commit.trim().to_string()).filter(|c| !c.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_clone_urls() {
+        assert!(is_git_url("https://github.com/o/r.git"));
+        assert!(is_git_url("git@github.com:o/r.git"));
+        assert!(is_git_url("file:///srv/git/r"));
+        assert!(!is_git_url("/srv/git/r"));
+        assert!(!is_git_url("api"));
+        assert!(!is_git_url("https://x y"));
+    }
+
+    #[test]
+    fn repo_name_variants() {
+        assert_eq!(
+            repo_name("https://github.com/o/api.git").as_deref(),
+            Some("api")
+        );
+        assert_eq!(
+            repo_name("https://github.com/o/api/").as_deref(),
+            Some("api")
+        );
+        assert_eq!(
+            repo_name("git@github.com:o/web.git").as_deref(),
+            Some("web")
+        );
+        assert_eq!(repo_name("git@host:solo.git").as_deref(), Some("solo"));
+        assert_eq!(repo_name("file:///srv/git/lib").as_deref(), Some("lib"));
+        assert_eq!(repo_name("https://"), None);
+        assert_eq!(repo_name("https://host/org/.."), None);
+        assert_eq!(repo_name("https://host/org/./"), None);
+        assert_eq!(repo_name("git@host:.git"), None);
+    }
+}
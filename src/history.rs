@@ -0,0 +1,243 @@
+use crate::config;
+use crate::connect::{self, ConnectOptions};
+use crate::shell::Shell;
+use crate::tmux;
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+/// Entries kept in the history file; older ones are dropped on write.
+const MAX_ENTRIES: usize = 200;
+
+/// One session nitro attached to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Visit {
+    /// Unix time of the connect
+    pub time: u64,
+    /// Socket name for sessions on another server
+    pub server: Option<String>,
+    pub session: String,
+}
+
+impl Visit {
+    fn parse(line: &str) -> Option<Visit> {
+        let mut fields = line.splitn(3, '\t');
+        let time = fields.next()?.parse().ok()?;
+        let server = fields.next()?;
+        let session = fields.next()?.trim_end();
+        if session.is_empty() {
+            return None;
+        }
+        Some(Visit {
+            time,
+            server: Some(server.to_string()).filter(|s| !s.is_empty()),
+            session: session.to_string(),
+        })
+    }
+
+    fn line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\n",
+            self.time,
+            self.server.as_deref().unwrap_or(""),
+            self.session
+        )
+    }
+
+    fn same_session(&self, other: &Visit) -> bool {
+        self.server == other.server && self.session == other.session
+    }
+}
+
+pub fn history_path<S: Shell>(sh: &S) -> Option<PathBuf> {
+    config::data_dir(sh).map(|d| d.join("history"))
+}
+
+/// Visits, oldest first. No file means no history.
+pub fn load<S: Shell>(sh: &S) -> Result<Vec<Visit>> {
+    let Some(path) = history_path(sh) else {
+        return Ok(Vec::new());
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(text.lines().filter_map(Visit::parse).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Append a visit. Repeating the latest visit only updates its time.
+pub fn record<S: Shell>(sh: &S, server: Option<&str>, session: &str) -> Result<()> {
+    let path = history_path(sh).context("no data directory for the history")?;
+    let visit = Visit {
+        time: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        server: server.map(str::to_string),
+        session: session.to_string(),
+    };
+    let mut visits = load(sh)?;
+    if visits.last().is_some_and(|v| v.same_session(&visit)) {
+        visits.pop();
+    }
+    visits.push(visit);
+    let skip = visits.len().saturating_sub(MAX_ENTRIES);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let text: String = visits[skip..].iter().map(Visit::line).collect();
+    std::fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Distinct sessions, most recent first, leaving out `current`.
+pub fn recent(visits: &[Visit], current: Option<&str>) -> Vec<Visit> {
+    let mut out: Vec<Visit> = Vec::new();
+    for v in visits.iter().rev() {
+        if v.server.is_none() && Some(v.session.as_str()) == current {
+            continue;
+        }
+        if !out.iter().any(|o| o.same_session(v)) {
+            out.push(v.clone());
+        }
+    }
+    out
+}
+
+fn still_exists<S: Shell>(sh: &S, v: &Visit) -> bool {
+    let res = match &v.server {
+        Some(server) => {
+            let socket = tmux::Socket::Name(server.clone());
+            tmux::has_session(&tmux::WithSocket::new(sh, &socket), &v.session)
+        }
+        None => tmux::has_session(sh, &v.session),
+    };
+    res.unwrap_or(false)
+}
+
+/// Connect to the `n`th most recent session other than the current one
+/// (`n` = 1 is `nitro last`), skipping sessions that no longer exist.
+pub fn run_back<S: Shell>(sh: &S, opts: ConnectOptions, n: usize) -> Result<()> {
+    if n == 0 {
+        anyhow::bail!("N must be at least 1");
+    }
+    let current = if sh.env_var("TMUX").is_some() {
+        tmux::active_session(sh)?
+    } else {
+        None
+    };
+    let visits = load(sh)?;
+    let target = recent(&visits, current.as_deref())
+        .into_iter()
+        .filter(|v| still_exists(sh, v))
+        .nth(n - 1);
+    let Some(v) = target else {
+        anyhow::bail!("no previous session to go back to");
+    };
+    connect::connect_on(sh, &opts, v.server.as_deref(), v.session, None, "history")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::ReplayShell;
+
+    fn visit(server: Option<&str>, session: &str) -> Visit {
+        Visit {
+            time: 0,
+            server: server.map(str::to_string),
+            session: session.into(),
+        }
+    }
+
+    fn back_opts() -> ConnectOptions {
+        ConnectOptions {
+            tokens: vec![],
+            dir: None,
+            mkdir: false,
+            projects_root: None,
+            hooks: Default::default(),
+            env: Default::default(),
+            refresh_env: crate::config::RefreshEnvConfig {
+                vars: vec![],
+                broadcast: false,
+            },
+            history: false,
+            multi: false,
+            attach: None,
+            key: false,
+            replay: false,
+        }
+    }
+
+    fn names(v: &[Visit]) -> Vec<&str> {
+        v.iter().map(|v| v.session.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_and_format_lines() {
+        let v = Visit::parse("1700000000\twork\tapi\n").unwrap();
+        assert_eq!(v.server.as_deref(), Some("work"));
+        assert_eq!(v.line(), "1700000000\twork\tapi\n");
+        let v = Visit::parse("5\t\tmy web").unwrap();
+        assert_eq!((v.server, v.session.as_str()), (None, "my web"));
+        assert!(Visit::parse("garbage").is_none());
+        assert!(Visit::parse("x\t\tapi").is_none());
+    }
+
+    #[test]
+    fn recent_dedups_and_skips_current() {
+        let visits = [
+            visit(None, "a"),
+            visit(None, "b"),
+            visit(Some("work"), "a"),
+            visit(None, "a"),
+            visit(None, "c"),
+        ];
+        let r = recent(&visits, Some("c"));
+        assert_eq!(names(&r), ["a", "a", "b"]);
+        assert_eq!(r[1].server.as_deref(), Some("work"));
+        assert_eq!(names(&recent(&visits, None))[0], "c");
+    }
+
+    #[test]
+    fn record_collapses_repeats() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("nitro-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let sh = ReplayShell::default().env("NITRO_DATA_DIR", &dir.to_string_lossy());
+        assert!(load(&sh)?.is_empty());
+        record(&sh, None, "a")?;
+        record(&sh, None, "b")?;
+        record(&sh, None, "b")?;
+        record(&sh, Some("work"), "b")?;
+        let visits = load(&sh)?;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(names(&visits), ["a", "b", "b"]);
+        assert_eq!(visits[2].server.as_deref(), Some("work"));
+        Ok(())
+    }
+
+    #[test]
+    fn back_skips_sessions_that_are_gone() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("nitro-back-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let has = |sh: ReplayShell, name: &str, ok: bool| {
+            sh.status("tmux", &["has-session", "-t", &format!("={}", name)], ok)
+        };
+        let mut sh = ReplayShell::default().env("NITRO_DATA_DIR", &dir.to_string_lossy());
+        for _ in 0..2 {
+            sh = has(has(has(sh, "gone", false), "b", true), "a", true);
+        }
+        let sh = has(sh, "a", true).tty("tmux", &["attach", "-t", "=a"]);
+        record(&sh, None, "a")?;
+        record(&sh, None, "b")?;
+        record(&sh, None, "gone")?;
+        // outside tmux there is no current session: 1 is "b", 2 is "a"
+        run_back(&sh, back_opts(), 2)?;
+        let too_far = run_back(&sh, back_opts(), 3);
+        std::fs::remove_dir_all(&dir)?;
+        assert!(too_far.is_err());
+        assert!(sh.unused().is_empty(), "{:?}", sh.unused());
+        Ok(())
+    }
+}
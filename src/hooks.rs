@@ -0,0 +1,165 @@
+use crate::config::HooksConfig;
+use crate::error;
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+
+/// Points in `nitro connect` where a configured hook runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Hook {
+    PreCreate,
+    PostCreate,
+    PreAttach,
+    PostDetach,
+}
+
+impl Hook {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PreCreate => "pre_create",
+            Hook::PostCreate => "post_create",
+            Hook::PreAttach => "pre_attach",
+            Hook::PostDetach => "post_detach",
+        }
+    }
+
+    fn command(self, hooks: &HooksConfig) -> Option<&str> {
+        match self {
+            Hook::PreCreate => hooks.pre_create.as_deref(),
+            Hook::PostCreate => hooks.post_create.as_deref(),
+            Hook::PreAttach => hooks.pre_attach.as_deref(),
+            Hook::PostDetach => hooks.post_detach.as_deref(),
+        }
+        .filter(|c| !c.trim().is_empty())
+    }
+
+    /// A failing `pre_*` hook aborts the connect; `post_*` failures only warn.
+    fn is_blocking(self) -> bool {
+        matches!(self, Hook::PreCreate | Hook::PreAttach)
+    }
+}
+
+/// Prefix of every hook script: a hook must never act on whatever directory
+/// nitro happens to run in.
+const CD_SESSION_DIR: &str = "cd \"${NITRO_DIR:?no session directory}\" || exit 1; ";
+
+/// What a hook is told about the session, exported as `NITRO_*` variables.
+pub struct HookContext<'a> {
+    pub session: &'a str,
+    pub dir: &'a str,
+    /// Where the directory came from: dir, line, clone, zoxide, home or existing
+    pub source: &'a str,
+}
+
+/// Run a configured hook through `sh -c` in the session directory, with
+/// `NITRO_HOOK`, `NITRO_SESSION`, `NITRO_DIR` and `NITRO_SOURCE` set. The
+/// hook fails without running when the directory is unknown or cannot be
+/// entered. `pre_*` hooks run on the terminal, so they can print and prompt.
+pub fn run<S: Shell>(sh: &S, hooks: &HooksConfig, hook: Hook, ctx: &HookContext) -> Result<()> {
+    let Some(cmd) = hook.command(hooks) else {
+        return Ok(());
+    };
+    let script = format!("{}{}", CD_SESSION_DIR, cmd);
+    let vars = [
+        format!("NITRO_HOOK={}", hook.name()),
+        format!("NITRO_SESSION={}", ctx.session),
+        format!("NITRO_DIR={}", ctx.dir),
+        format!("NITRO_SOURCE={}", ctx.source),
+    ];
+    let mut args: Vec<&str> = vars.iter().map(String::as_str).collect();
+    args.extend(["sh", "-c", &script]);
+    let res = if hook.is_blocking() {
+        sh.run_interactive("env", &args)
+    } else {
+        sh.run("env", &args).map(|_| ())
+    };
+    let res = res.with_context(|| format!("{} hook failed", hook.name()));
+    match res {
+        Err(e) if !hook.is_blocking() => {
+            error::warn(&e);
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::NitroError;
+    use crate::transcript::ReplayShell;
+
+    /// Arguments to `env` for running `command` as the `hook` of `ctx()`.
+    fn hook_args(hook: &str, command: &str) -> Vec<String> {
+        vec![
+            format!("NITRO_HOOK={}", hook),
+            "NITRO_SESSION=api".into(),
+            "NITRO_DIR=/src/api".into(),
+            "NITRO_SOURCE=zoxide".into(),
+            "sh".into(),
+            "-c".into(),
+            format!("{}{}", CD_SESSION_DIR, command),
+        ]
+    }
+
+    fn ctx() -> HookContext<'static> {
+        HookContext {
+            session: "api",
+            dir: "/src/api",
+            source: "zoxide",
+        }
+    }
+
+    #[test]
+    fn runs_configured_hook_with_env() -> Result<()> {
+        let args = hook_args("post_create", "git fetch");
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let sh = ReplayShell::default().output("env", &args, "");
+        let hooks = HooksConfig {
+            post_create: Some("git fetch".into()),
+            ..Default::default()
+        };
+        run(&sh, &hooks, Hook::PreCreate, &ctx())?;
+        run(&sh, &hooks, Hook::PostCreate, &ctx())?;
+        assert!(sh.unused().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn hooks_never_run_outside_the_session_dir() -> Result<()> {
+        use crate::shell::RealShell;
+        let script = format!("{}pwd", CD_SESSION_DIR);
+        let run_in = |dir: &str| {
+            let var = format!("NITRO_DIR={}", dir);
+            RealShell.run("env", &[&var, "sh", "-c", &script])
+        };
+        let tmp = std::env::temp_dir().canonicalize()?;
+        assert_eq!(
+            run_in(&tmp.to_string_lossy())?.trim(),
+            tmp.to_string_lossy()
+        );
+        assert!(run_in("").is_err());
+        assert!(run_in("/nitro/no/such/dir").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn pre_hooks_block_post_hooks_warn() {
+        let sh = ["pre_attach", "post_detach"]
+            .iter()
+            .fold(ReplayShell::default(), |sh, hook| {
+                let args = hook_args(hook, "false");
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                let err = NitroError::command_failed("env", &args, "exit 1");
+                sh.fail("env", &args, err)
+            });
+        let hooks = HooksConfig {
+            pre_attach: Some("false".into()),
+            post_detach: Some("false".into()),
+            ..Default::default()
+        };
+        assert!(run(&sh, &hooks, Hook::PreAttach, &ctx()).is_err());
+        assert!(run(&sh, &hooks, Hook::PostDetach, &ctx()).is_ok());
+        assert!(sh.unused().is_empty());
+    }
+}
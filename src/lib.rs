@@ -0,0 +1,88 @@
+//! List tmux sessions, pinned entries and zoxide directories, and connect to
+//! them. The `nitro` binary is a thin command-line layer over this crate.
+//!
+//! The main entry points:
+//!
+//! - [`Shell`]: every external command goes through it; [`RealShell`] runs
+//!   real processes, and tests can substitute their own.
+//! - [`list::build_entries`]: the candidate model, one [`list::Entry`] per
+//!   pin, tmux session or zoxide directory, tagged with its [`list::Source`].
+//! - [`connect::parse_connect_line`] and [`connect::normalize_name`]: turn a
+//!   list line into a session name, directory and server.
+//! - [`connect::run_connect`]: create and attach to sessions.
+//!
+//! ```
+//! let tokens = ["[z]".to_string(), "/src/my api".to_string()];
+//! let line = nitro::connect::parse_connect_line(&tokens);
+//! assert_eq!(line.name, "my-api");
+//! assert_eq!(line.path.unwrap().to_str(), Some("/src/my api"));
+//! ```
+//!
+//! Structs and enums that are expected to grow (options, configuration,
+//! list entries, errors) are `#[non_exhaustive]`: build options with their
+//! constructors or `Default` and set fields, and keep a wildcard arm when
+//! matching. Adding a field or variant is then not a breaking change.
+//!
+//! Everything else (rendering, fzf, hooks, snapshots, the transcript shells)
+//! is internal to the binary and may change in any release.
+
+#![warn(missing_docs)]
+
+// The `nitro` binary: dispatching parsed arguments to the modules below.
+mod app;
+// Command-line arguments.
+mod cli;
+/// The `config.toml` settings.
+pub mod config;
+/// Parsing list lines and creating, attaching and cloning sessions.
+pub mod connect;
+// Typed errors, exit codes and error reporting.
+mod error;
+// Include/exclude patterns.
+mod filter;
+// The `nitro fzf` launcher and its key bindings.
+mod fzf;
+// Killing idle sessions.
+mod gc;
+// Git URL detection and cloning.
+mod git;
+// The connect history behind `nitro last` and `nitro back`.
+mod history;
+// Shell hooks run around connect.
+mod hooks;
+/// Building and rendering `nitro list`.
+pub mod list;
+// Expanding and normalizing paths typed on the command line.
+mod paths;
+// Pinned entries.
+mod pins;
+// Project types told by marker files.
+mod project;
+// `.env` and direnv variables for new sessions.
+mod session_env;
+// The command runner abstraction.
+mod shell;
+// Saving and restoring sessions.
+mod snapshot;
+// The `nitro status` segment for the tmux status line.
+mod status;
+// `nitro list` line templates.
+mod template;
+// Color detection and themes.
+mod theme;
+// tmux commands and server selection.
+mod tmux;
+// Recording shell calls to a transcript and replaying them.
+mod transcript;
+// zoxide queries and maintenance.
+mod zoxide;
+
+pub use error::NitroError;
+pub use shell::{RealShell, Shell};
+
+/// Run the `nitro` command line on the process arguments; the whole of the
+/// binary's `main`. Not part of the library API.
+#[doc(hidden)]
+pub fn run_cli() -> std::process::ExitCode {
+    app::main()
+}
@@ -1,71 +1,445 @@
 use crate::connect::normalize_name;
+use crate::error;
+use crate::filter::Filters;
+use crate::gc;
+use crate::git;
+use crate::pins;
+pub use crate::project::ProjectType;
 use crate::shell::Shell;
+use crate::template::{Field, Template, Templates, pad, truncate};
+use crate::theme::{Paint, Style};
 use crate::tmux;
 use crate::zoxide;
 use anyhow::Result;
+use serde::Serialize;
 use std::collections::HashSet;
+use std::path::PathBuf;
+use unicode_width::UnicodeWidthStr;
 
-const ICON_TMUX: &str = "";
+const ICON_PIN: &str = "";
+pub(crate) const ICON_TMUX: &str = "";
 const ICON_ZOX: &str = "";
-const COLOR_TMUX: &str = "\u{001b}[35m";
-const COLOR_ZOX: &str = "\u{001b}[34m";
-const COLOR_RESET: &str = "\u{001b}[0m";
 
+/// What `nitro list` lists and how; [`Default`] is the flagless command.
+#[non_exhaustive]
 pub struct ListOptions {
+    /// List pinned entries
+    pub include_pins: bool,
+    /// List tmux sessions
     pub include_tmux: bool,
+    /// List zoxide directories
     pub include_zox: bool,
+    /// List at most this many zoxide directories
     pub z_limit: Option<usize>,
+    /// Prefix lines with Nerd Font icons
     pub icons: bool,
-    pub no_color: bool,
+    /// Theme and color depth; plain text by default
+    pub(crate) paint: Paint,
+    /// Fail when a source errors instead of warning and listing the rest
+    pub strict: bool,
+    /// List sessions of every server socket, labelled "server:name"
+    pub all_servers: bool,
+    /// Include/exclude rules applied per source (pins are never filtered)
+    pub(crate) filters: Filters,
+    /// Drop zoxide entries whose directory no longer exists
+    pub prune_missing: bool,
+    /// File keeping those directory checks between runs; `None` checks
+    /// every time
+    pub(crate) dir_cache: Option<PathBuf>,
+    /// Drop zoxide entries scoring below this
+    pub min_score: Option<f64>,
+    /// Append zoxide scores to lines
+    pub(crate) show_scores: bool,
+    /// Print entries as a JSON array instead of lines
+    pub(crate) json: bool,
+    /// Prefix each line with its key and a tab, for `nitro connect --key`
+    pub(crate) delimited: bool,
+    /// Aligned columns with tmux session details
+    pub(crate) long: bool,
+    /// Terminal width `--long` lines are fitted to; `None` never truncates
+    pub(crate) width: Option<usize>,
+    /// Unix time ages in `--long` lines are relative to
+    pub(crate) now: u64,
+    /// Line templates per source; `--long` takes precedence
+    pub(crate) templates: Templates,
+    /// Keep only entries whose directory is one of these project types
+    pub types: Vec<ProjectType>,
 }
 
-fn colorize(enabled: bool, color: &str, s: &str) -> String {
-    if enabled {
-        format!("{}{}{}", color, s, COLOR_RESET)
+impl ListOptions {
+    /// Whether tmux entries need their details (path, windows, clients).
+    fn session_details(&self) -> bool {
+        let shown = [Field::Path, Field::Branch, Field::Windows, Field::Attached];
+        self.long
+            || !self.types.is_empty()
+            || self
+                .templates
+                .tmux
+                .as_ref()
+                .is_some_and(|t| shown.iter().any(|f| t.uses(*f)))
+    }
+
+    /// Whether entries need their project type: it picks the icon, shows
+    /// in JSON and templates, and `--type` filters by it.
+    fn project_types(&self) -> bool {
+        self.icons
+            || self.json
+            || !self.types.is_empty()
+            || [
+                &self.templates.pin,
+                &self.templates.tmux,
+                &self.templates.zoxide,
+            ]
+            .iter()
+            .any(|t| t.as_ref().is_some_and(|t| t.uses(Field::Project)))
+    }
+
+    /// Whether `--type` lets an entry of project type `project` through.
+    fn allows_type(&self, project: Option<ProjectType>) -> bool {
+        self.types.is_empty() || project.is_some_and(|p| self.types.contains(&p))
+    }
+}
+
+impl Default for ListOptions {
+    /// What `nitro list` does without flags: every source, ASCII markers,
+    /// missing zoxide directories skipped, no colors (the binary detects
+    /// them).
+    fn default() -> Self {
+        ListOptions {
+            include_pins: true,
+            include_tmux: true,
+            include_zox: true,
+            z_limit: None,
+            icons: false,
+            paint: Paint::default(),
+            strict: false,
+            all_servers: false,
+            filters: Filters::default(),
+            prune_missing: true,
+            dir_cache: None,
+            min_score: None,
+            show_scores: false,
+            json: false,
+            delimited: false,
+            long: false,
+            width: None,
+            templates: Templates::default(),
+            types: Vec::new(),
+            now: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Non-strict mode reports a failing source on stderr and keeps listing the
+/// others; strict mode turns the failure into an error.
+fn source_result<T>(strict: bool, res: Result<T>) -> Result<Option<T>> {
+    match res {
+        Ok(v) => Ok(Some(v)),
+        Err(e) if strict => Err(e),
+        Err(e) => {
+            error::warn(&e);
+            Ok(None)
+        }
+    }
+}
+
+/// Sessions of the server `sh` talks to, with the active one first. With
+/// `long` every session carries its tmux details.
+fn server_sessions<S: Shell>(
+    sh: &S,
+    strict: bool,
+    long: bool,
+) -> Result<Vec<(String, Option<SessionMeta>)>> {
+    let listing = if long {
+        tmux::list_session_info(sh).map(|mut infos| {
+            infos.sort_by(|a, b| a.name.cmp(&b.name));
+            infos
+                .into_iter()
+                .map(|i| {
+                    let meta = SessionMeta {
+                        path: Some(PathBuf::from(&i.path)).filter(|_| !i.path.is_empty()),
+                        windows: i.windows,
+                        attached: i.attached,
+                        last_used: i.last_used(),
+                        active: false,
+                    };
+                    (i.name, Some(meta))
+                })
+                .collect()
+        })
     } else {
-        s.to_string()
+        tmux::list_sessions(sh).map(|names| names.into_iter().map(|n| (n, None)).collect())
+    };
+    let Some(mut sessions): Option<Vec<(String, Option<SessionMeta>)>> =
+        source_result(strict, listing)?
+    else {
+        return Ok(Vec::new());
+    };
+    // Promote active session to the front if present
+    if let Some(active) = source_result(strict, tmux::active_session(sh))?.flatten()
+        && let Some(i) = sessions.iter().position(|(s, _)| s == &active)
+    {
+        let mut s = sessions.remove(i);
+        if let Some(meta) = &mut s.1 {
+            meta.active = true;
+        }
+        sessions.insert(0, s);
+    }
+    Ok(sessions)
+}
+
+/// Where a list entry comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Source {
+    /// A pinned directory or session name
+    Pin,
+    /// A running tmux session
+    Tmux,
+    /// A zoxide directory
+    Zoxide,
+}
+
+/// One candidate in `nitro list`, before rendering.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct Entry {
+    /// Where the entry comes from
+    pub source: Source,
+    /// Session name connecting to this entry would use
+    pub name: String,
+    /// Directory of a pin or zoxide entry, or a session's start directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// tmux server socket, when listing every server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    /// zoxide frecency score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// tmux details of a session, with `--long`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionMeta>,
+    /// Project type of the directory, from its marker files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<ProjectType>,
+}
+
+/// Details of a tmux session entry, collected for `--long`.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct SessionMeta {
+    /// Session start directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Number of windows
+    pub windows: u32,
+    /// Number of attached clients
+    pub attached: u32,
+    /// Unix time of the last activity or attach
+    pub last_used: u64,
+    /// The session of the client nitro runs in (or the attached one)
+    pub active: bool,
+}
+
+/// Escape backslashes, tabs and newlines so a key stays in one column.
+fn escape_key(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape_key(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
     }
+    out
 }
 
-pub fn build_list_lines<S: Shell>(sh: &S, opts: &ListOptions) -> Result<Vec<String>> {
-    let mut lines = Vec::new();
+/// A decoded [`Entry::key`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub(crate) struct Key {
+    pub source: Source,
+    /// tmux server socket name; tmux keys only
+    pub server: Option<String>,
+    /// Exact session name or path
+    pub id: String,
+}
 
-    // Color policy: color enabled by default; disable with --no-color only
-    // For ASCII mode: color prefixes; for icon mode: color glyphs
-    let want_color = !opts.no_color;
+/// Decode a key written by `nitro list --delimited`.
+pub(crate) fn parse_key(key: &str) -> Option<Key> {
+    let (source, rest) = key.split_once(':')?;
+    let (source, server, id) = match source {
+        "p" => (Source::Pin, None, rest),
+        "z" => (Source::Zoxide, None, rest),
+        "t" => {
+            // Session names cannot contain ':', so the last one separates
+            let (server, name) = rest.rsplit_once(':')?;
+            let server = Some(unescape_key(server)).filter(|s| !s.is_empty());
+            (Source::Tmux, server, name)
+        }
+        _ => return None,
+    };
+    let id = unescape_key(id);
+    if id.is_empty() {
+        return None;
+    }
+    Some(Key { source, server, id })
+}
 
-    // Track normalized tmux session names to filter zoxide duplicates
+impl Entry {
+    /// Exact identity of the entry, free of tabs and newlines:
+    /// `p:<path or session>`, `t:<server>:<session>` (empty server for the
+    /// current one) or `z:<path>`.
+    pub fn key(&self) -> String {
+        match self.source {
+            Source::Tmux => format!(
+                "t:{}:{}",
+                escape_key(self.server.as_deref().unwrap_or("")),
+                escape_key(&self.name)
+            ),
+            Source::Pin => format!("p:{}", escape_key(&self.text())),
+            Source::Zoxide => format!("z:{}", escape_key(&self.text())),
+        }
+    }
+
+    /// Text after the source marker, as understood by `nitro connect`.
+    pub fn text(&self) -> String {
+        match (self.source, &self.path, &self.server) {
+            (Source::Tmux, _, Some(server)) => format!("{}:{}", server, self.name),
+            (Source::Tmux, _, None) => self.name.clone(),
+            (_, Some(p), _) => p.display().to_string(),
+            (_, None, _) => self.name.clone(),
+        }
+    }
+}
+
+/// Collect the list candidates in display order: pins, then tmux sessions,
+/// then zoxide directories, each deduplicated against the ones before.
+pub fn build_entries<S: Shell>(sh: &S, opts: &ListOptions) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+
+    // Track normalized session names already listed (pins, then tmux) to
+    // filter duplicates from later sources
     let mut tmux_names: HashSet<String> = HashSet::new();
+    let mut pinned_names: HashSet<String> = HashSet::new();
+    let mut pinned_paths: HashSet<PathBuf> = HashSet::new();
 
-    if opts.include_tmux
-        && let Ok(mut sessions) = tmux::list_sessions(sh)
+    if opts.include_pins
+        && let Some(pins) = source_result(opts.strict, pins::load(sh))?
     {
-        // Promote active session to the front if present
-        if let Some(active) = tmux::active_session(sh)
-            && let Some(i) = sessions.iter().position(|s| s == &active)
-        {
-            let s = sessions.remove(i);
-            sessions.insert(0, s);
-        }
-        for s in sessions.drain(..) {
-            tmux_names.insert(normalize_name(&s));
-            if opts.icons {
-                let icon = colorize(want_color, COLOR_TMUX, ICON_TMUX);
-                lines.push(format!("{} {}", icon, s));
-            } else {
-                let prefix = colorize(want_color, COLOR_TMUX, "[t]");
-                lines.push(format!("{} {}", prefix, s));
+        for pin in pins {
+            let name = pin.session_name();
+            pinned_names.insert(name.clone());
+            let path = match pin {
+                pins::Pin::Path(p) => {
+                    pinned_paths.insert(p.clone());
+                    Some(p)
+                }
+                pins::Pin::Session(_) => None,
+            };
+            let project = path
+                .as_deref()
+                .filter(|_| opts.project_types())
+                .and_then(ProjectType::detect);
+            if !opts.allows_type(project) {
+                continue;
+            }
+            entries.push(Entry {
+                source: Source::Pin,
+                name,
+                path,
+                server: None,
+                score: None,
+                session: None,
+                project,
+            });
+        }
+    }
+
+    if opts.include_tmux {
+        // Sessions carry their server only when listing every server
+        let mut sessions = Vec::new();
+        if opts.all_servers {
+            let servers = source_result(opts.strict, tmux::server_sockets(sh))?;
+            for server in servers.unwrap_or_default() {
+                let ssh = tmux::WithSocket::new(sh, &tmux::Socket::Name(server.clone()));
+                for (s, meta) in server_sessions(&ssh, opts.strict, opts.session_details())? {
+                    sessions.push((Some(server.clone()), s, meta));
+                }
+            }
+        } else {
+            for (s, meta) in server_sessions(sh, opts.strict, opts.session_details())? {
+                sessions.push((None, s, meta));
+            }
+        }
+        for (server, s, meta) in sessions {
+            if !opts.filters.tmux.allows(&s) {
+                continue;
+            }
+            let name = normalize_name(&s);
+            if pinned_names.contains(&name) {
+                continue; // already listed as a pin
+            }
+            tmux_names.insert(name);
+            let project = meta
+                .as_ref()
+                .and_then(|m| m.path.as_deref())
+                .filter(|_| opts.project_types())
+                .and_then(ProjectType::detect);
+            if !opts.allows_type(project) {
+                continue;
             }
+            entries.push(Entry {
+                source: Source::Tmux,
+                name: s,
+                path: None,
+                server,
+                score: None,
+                session: meta,
+                project,
+            });
         }
     }
 
     if opts.include_zox
-        && let Ok(mut items) = zoxide::list_all(sh)
+        && let Some(items) = source_result(opts.strict, zoxide::list_all(sh))?
     {
-        if let Some(n) = opts.z_limit {
-            items.truncate(n);
-        }
-        for it in items.into_iter() {
+        // Stat lazily: with -z N only as many entries as needed are checked
+        let dirs = zoxide::DirCache::load(opts.dir_cache.as_deref(), opts.now);
+        let items = items
+            .into_iter()
+            .filter(|it| {
+                opts.min_score
+                    .is_none_or(|min| it.score.is_none_or(|s| s >= min))
+            })
+            .filter(|it| opts.filters.zoxide.allows(&it.path.to_string_lossy()))
+            .filter(|it| !opts.prune_missing || dirs.is_dir(&it.path))
+            .map(|it| {
+                let project = Some(&it.path)
+                    .filter(|_| opts.project_types())
+                    .and_then(|p| ProjectType::detect(p));
+                (it, project)
+            })
+            .filter(|(_, project)| opts.allows_type(*project))
+            .take(opts.z_limit.unwrap_or(usize::MAX));
+        for (it, project) in items {
             // Derive a would-be session name from the path's basename
             let base = it
                 .path
@@ -76,20 +450,249 @@ pub fn build_list_lines<S: Shell>(sh: &S, opts: &ListOptions) -> Result<Vec<Stri
             if tmux_names.contains(&z_name) {
                 continue; // skip duplicates matching existing tmux sessions
             }
-            if opts.icons {
-                let icon = colorize(want_color, COLOR_ZOX, ICON_ZOX);
-                lines.push(format!("{} {}", icon, it.path.display()));
-            } else {
-                let prefix = colorize(want_color, COLOR_ZOX, "[z]");
-                lines.push(format!("{} {}", prefix, it.path.display()));
+            if pinned_paths.contains(&it.path) || pinned_names.contains(&z_name) {
+                continue; // already listed as a pin
             }
+            entries.push(Entry {
+                source: Source::Zoxide,
+                name: z_name,
+                path: Some(it.path),
+                server: None,
+                score: it.score,
+                session: None,
+                project,
+            });
         }
+        dirs.save();
     }
 
-    Ok(lines)
+    Ok(entries)
+}
+
+/// The source marker (`[t]`, or the glyph with `--icons`) and its style.
+/// Directories of a known project type show that type's glyph instead;
+/// the color still tells the source.
+fn marker_and_style<'a>(entry: &Entry, opts: &'a ListOptions) -> (&'static str, &'a Style) {
+    let theme = &opts.paint.theme;
+    let (style, icon, prefix) = match entry.source {
+        Source::Pin => (&theme.pin, ICON_PIN, "[p]"),
+        Source::Tmux => (&theme.tmux, ICON_TMUX, "[t]"),
+        Source::Zoxide => (&theme.zoxide, ICON_ZOX, "[z]"),
+    };
+    let icon = match (entry.source, entry.project) {
+        (Source::Pin | Source::Zoxide, Some(project)) => project.icon(),
+        _ => icon,
+    };
+    (if opts.icons { icon } else { prefix }, style)
+}
+
+fn source_marker(entry: &Entry, opts: &ListOptions) -> String {
+    let (marker, style) = marker_and_style(entry, opts);
+    opts.paint.style(style, marker)
+}
+
+/// Style of a session's name: bold for the current session, dim when it is
+/// detached and unused for longer than the theme's stale age. Only known
+/// when session details were collected.
+fn name_style<'a>(entry: &Entry, opts: &'a ListOptions) -> Option<&'a Style> {
+    let meta = entry.session.as_ref()?;
+    let theme = &opts.paint.theme;
+    if meta.active {
+        Some(&theme.active)
+    } else if meta.attached == 0
+        && meta.last_used > 0
+        && opts.now.saturating_sub(meta.last_used) > theme.stale_after
+    {
+        Some(&theme.stale)
+    } else {
+        None
+    }
+}
+
+/// Trailing ` [score]` annotation, with `--scores`; connect strips it.
+fn score_suffix(entry: &Entry, opts: &ListOptions) -> String {
+    match entry.score {
+        Some(score) if opts.show_scores => format!(" [{:.1}]", score),
+        _ => String::new(),
+    }
+}
+
+fn render_line(entry: &Entry, opts: &ListOptions) -> String {
+    format!(
+        "{} {}{}",
+        source_marker(entry, opts),
+        entry.text(),
+        score_suffix(entry, opts)
+    )
+}
+
+/// Widest name column in `--long` lines; longer names are cut.
+const LONG_NAME_MAX: usize = 32;
+
+/// The `--long` columns of an entry: state, name, windows, clients, age
+/// and path. Only tmux sessions have the middle ones.
+fn long_columns(entry: &Entry, now: u64) -> [String; 6] {
+    let name = match entry.source {
+        Source::Tmux => entry.text(),
+        _ => entry.name.clone(),
+    };
+    let path = entry
+        .path
+        .as_ref()
+        .or(entry.session.as_ref().and_then(|m| m.path.as_ref()))
+        .map(|p| p.display().to_string())
+        .unwrap_or_default();
+    let Some(meta) = &entry.session else {
+        return [" ".into(), name, "".into(), "".into(), "".into(), path];
+    };
+    let state = if meta.active {
+        "*"
+    } else if meta.attached > 0 {
+        "+"
+    } else {
+        " "
+    };
+    let clients = match meta.attached {
+        0 => String::new(),
+        1 => "1 client".to_string(),
+        n => format!("{} clients", n),
+    };
+    let age = match meta.last_used {
+        0 => String::new(),
+        t => format!("{} ago", gc::format_age(now.saturating_sub(t))),
+    };
+    [
+        state.into(),
+        name,
+        format!("{}w", meta.windows),
+        clients,
+        age,
+        path,
+    ]
+}
+
+/// Render entries as aligned columns, fitting the path to `opts.width`.
+fn render_long(entries: &[Entry], opts: &ListOptions) -> Vec<String> {
+    let rows: Vec<[String; 6]> = entries.iter().map(|e| long_columns(e, opts.now)).collect();
+    let col = |i: usize| rows.iter().map(|r| r[i].width()).max().unwrap_or(0);
+    let name_w = col(1).min(LONG_NAME_MAX);
+    let (win_w, cli_w, age_w) = (col(2), col(3), col(4));
+    entries
+        .iter()
+        .zip(&rows)
+        .map(|(e, r)| {
+            let name = pad(&truncate(&r[1], name_w, false), name_w, false);
+            let details = format!(
+                "  {}  {}  {}  ",
+                pad(&r[2], win_w, true),
+                pad(&r[3], cli_w, false),
+                pad(&r[4], age_w, true),
+            );
+            let score = score_suffix(e, opts);
+            let path = match opts.width {
+                Some(width) => {
+                    // The source marker is one glyph or three ASCII columns
+                    let marker_w = if opts.icons { 1 } else { 3 };
+                    let used = marker_w + 3 + name_w + details.width() + score.width();
+                    truncate(&r[5], width.saturating_sub(used), true)
+                }
+                None => r[5].clone(),
+            };
+            let name = match name_style(e, opts) {
+                Some(style) => opts.paint.style(style, &name),
+                None => name,
+            };
+            let marker = source_marker(e, opts);
+            format!("{} {} {}{}{}{}", marker, r[0], name, details, path, score)
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+/// Render an entry with its source's template. `{branch}` asks git, once
+/// per entry.
+fn render_template<S: Shell>(
+    sh: &S,
+    entry: &Entry,
+    template: &Template,
+    opts: &ListOptions,
+) -> String {
+    let path = || {
+        entry
+            .path
+            .as_ref()
+            .or(entry.session.as_ref().and_then(|m| m.path.as_ref()))
+    };
+    let meta = entry.session.as_ref();
+    template.render(
+        |field| match field {
+            Field::Source => match entry.source {
+                Source::Pin => "pin".to_string(),
+                Source::Tmux => "tmux".to_string(),
+                Source::Zoxide => "zoxide".to_string(),
+            },
+            Field::Icon => marker_and_style(entry, opts).0.to_string(),
+            Field::Name => match entry.source {
+                Source::Tmux => entry.text(),
+                _ => entry.name.clone(),
+            },
+            Field::Path => path().map(|p| p.display().to_string()).unwrap_or_default(),
+            Field::Branch => path()
+                .and_then(|p| git::current_branch(sh, p))
+                .unwrap_or_default(),
+            Field::Windows => meta.map(|m| m.windows.to_string()).unwrap_or_default(),
+            Field::Attached => meta.map(|m| m.attached.to_string()).unwrap_or_default(),
+            Field::Score => entry.score.map(|s| format!("{:.1}", s)).unwrap_or_default(),
+            Field::Project => entry
+                .project
+                .map(|p| p.name().to_string())
+                .unwrap_or_default(),
+        },
+        |field, v| match field {
+            Field::Icon => opts.paint.style(marker_and_style(entry, opts).1, &v),
+            Field::Name => match name_style(entry, opts) {
+                Some(style) => opts.paint.style(style, &v),
+                None => v,
+            },
+            _ => v,
+        },
+    )
+}
+
+/// Candidates rendered as `nitro list` prints them.
+pub(crate) fn build_list_lines<S: Shell>(sh: &S, opts: &ListOptions) -> Result<Vec<String>> {
+    let entries = build_entries(sh, opts)?;
+    let lines = if opts.long {
+        render_long(&entries, opts)
+    } else {
+        entries
+            .iter()
+            .map(|e| match opts.templates.get(e.source) {
+                Some(t) => render_template(sh, e, t, opts),
+                None => render_line(e, opts),
+            })
+            .collect()
+    };
+    Ok(entries
+        .iter()
+        .zip(lines)
+        .map(|(e, line)| {
+            if opts.delimited {
+                format!("{}\t{}", e.key(), line)
+            } else {
+                line
+            }
+        })
+        .collect())
 }
 
-pub fn run_list<S: Shell>(sh: &S, opts: ListOptions) -> Result<()> {
+pub(crate) fn run_list<S: Shell>(sh: &S, opts: ListOptions) -> Result<()> {
+    if opts.json {
+        let entries = build_entries(sh, &opts)?;
+        println!("{}", serde_json::to_string(&entries)?);
+        return Ok(());
+    }
     for line in build_list_lines(sh, &opts)? {
         println!("{}", line);
     }
@@ -99,66 +702,46 @@ pub fn run_list<S: Shell>(sh: &S, opts: ListOptions) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::theme::{ColorDepth, Theme};
+    use crate::transcript::ReplayShell;
     use anyhow::Result;
-    use std::collections::HashMap;
-
-    #[derive(Default)]
-    struct MockShell {
-        map: HashMap<(String, Vec<String>), String>,
-        env: HashMap<String, String>,
-    }
 
-    impl MockShell {
-        fn with(mut self, program: &str, args: &[&str], output: &str) -> Self {
-            self.map.insert(
-                (program.into(), args.iter().map(|s| s.to_string()).collect()),
-                output.into(),
-            );
-            self
-        }
-        #[allow(dead_code)]
-        fn with_env(mut self, k: &str, v: &str) -> Self {
-            self.env.insert(k.into(), v.into());
-            self
-        }
-    }
-
-    impl crate::shell::Shell for MockShell {
-        fn run(&self, program: &str, args: &[&str]) -> Result<String> {
-            Ok(self
-                .map
-                .get(&(program.into(), args.iter().map(|s| s.to_string()).collect()))
-                .cloned()
-                .unwrap_or_default())
-        }
-        fn run_status(&self, _program: &str, _args: &[&str]) -> Result<bool> {
-            Ok(true)
-        }
-        fn run_tty(&self, _program: &str, _args: &[&str]) -> Result<()> {
-            Ok(())
-        }
-        fn env_var(&self, key: &str) -> Option<String> {
-            self.env.get(key).cloned()
-        }
-    }
+    /// `active_session`'s fallback when `display-message` names no session
+    const ATTACHED: &str = "#{?session_attached,1,0}\t#S";
 
     #[test]
     fn list_tmux_and_zoxide_ascii_and_limit() -> Result<()> {
-        let sh = MockShell::default()
-            .with("tmux", &["list-sessions", "-F", "#S"], "b\na\n")
-            .with("tmux", &["display-message", "-p", "-F", "#S"], "\n")
-            .with(
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", "#S"], "b\na\n")
+            .output("tmux", &["display-message", "-p", "-F", "#S"], "\n")
+            .output("tmux", &["list-sessions", "-F", ATTACHED], "")
+            .output(
                 "zoxide",
-                &["query", "-l"],
+                &["query", "-ls"],
                 "/home/u/one\n/home/u/two\n/home/u/three\n",
             );
 
         let opts = ListOptions {
+            include_pins: false,
             include_tmux: true,
             include_zox: true,
             z_limit: Some(2),
             icons: false,
-            no_color: true,
+            paint: Paint::default(),
+            strict: false,
+            all_servers: false,
+            filters: Filters::default(),
+            prune_missing: false,
+            dir_cache: None,
+            min_score: None,
+            show_scores: false,
+            json: false,
+            delimited: false,
+            long: false,
+            width: None,
+            now: 0,
+            templates: Templates::default(),
+            types: Vec::new(),
         };
         let lines = build_list_lines(&sh, &opts)?;
         assert_eq!(
@@ -176,10 +759,11 @@ mod tests {
     #[test]
     fn list_filters_zoxide_duplicates_matching_tmux() -> Result<()> {
         // tmux sessions a and b; zoxide lists dirs whose basenames are a, b, c
-        let sh = MockShell::default()
-            .with("tmux", &["list-sessions", "-F", "#S"], "b\na\n")
-            .with("tmux", &["display-message", "-p", "-F", "#S"], "\n")
-            .with("zoxide", &["query", "-l"], "/x/a\n/y/b\n/z/c\n");
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", "#S"], "b\na\n")
+            .output("tmux", &["display-message", "-p", "-F", "#S"], "\n")
+            .output("tmux", &["list-sessions", "-F", ATTACHED], "")
+            .output("zoxide", &["query", "-ls"], "/x/a\n/y/b\n/z/c\n");
         let lines = build_list_lines(&sh, &opts_ascii(true))?;
         assert_eq!(
             lines,
@@ -192,32 +776,69 @@ mod tests {
         Ok(())
     }
 
+    fn paint(no_color: bool) -> Paint {
+        Paint {
+            theme: Theme::default(),
+            depth: (!no_color).then_some(ColorDepth::Ansi16),
+        }
+    }
+
     fn opts_ascii(no_color: bool) -> ListOptions {
         ListOptions {
+            include_pins: false,
             include_tmux: true,
             include_zox: true,
             z_limit: None,
             icons: false,
-            no_color,
+            paint: paint(no_color),
+            strict: false,
+            all_servers: false,
+            filters: Filters::default(),
+            prune_missing: false,
+            dir_cache: None,
+            min_score: None,
+            show_scores: false,
+            json: false,
+            delimited: false,
+            long: false,
+            width: None,
+            now: 0,
+            templates: Templates::default(),
+            types: Vec::new(),
         }
     }
 
     fn opts_icons(no_color: bool) -> ListOptions {
         ListOptions {
+            include_pins: false,
             include_tmux: true,
             include_zox: true,
             z_limit: None,
             icons: true,
-            no_color,
+            paint: paint(no_color),
+            strict: false,
+            all_servers: false,
+            filters: Filters::default(),
+            prune_missing: false,
+            dir_cache: None,
+            min_score: None,
+            show_scores: false,
+            json: false,
+            delimited: false,
+            long: false,
+            width: None,
+            now: 0,
+            templates: Templates::default(),
+            types: Vec::new(),
         }
     }
 
     #[test]
     fn list_icons_with_color() -> Result<()> {
-        let sh = MockShell::default()
-            .with("tmux", &["list-sessions", "-F", "#S"], "x\n")
-            .with("tmux", &["display-message", "-p", "-F", "#S"], "x\n")
-            .with("zoxide", &["query", "-l"], "/a/b\n");
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", "#S"], "x\n")
+            .output("tmux", &["display-message", "-p", "-F", "#S"], "x\n")
+            .output("zoxide", &["query", "-ls"], "/a/b\n");
         let lines = build_list_lines(&sh, &opts_icons(false))?;
         assert_eq!(lines.len(), 2);
         assert!(lines[0].contains("\u{001b}["));
@@ -229,10 +850,11 @@ mod tests {
 
     #[test]
     fn list_graceful_empty() -> Result<()> {
-        let sh = MockShell::default()
-            .with("tmux", &["list-sessions", "-F", "#S"], "\n\n")
-            .with("tmux", &["display-message", "-p", "-F", "#S"], "\n")
-            .with("zoxide", &["query", "-l"], "\n");
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", "#S"], "\n\n")
+            .output("tmux", &["display-message", "-p", "-F", "#S"], "\n")
+            .output("tmux", &["list-sessions", "-F", ATTACHED], "")
+            .output("zoxide", &["query", "-ls"], "\n");
         let lines = build_list_lines(&sh, &opts_ascii(true))?;
         assert!(lines.is_empty());
         Ok(())
@@ -240,11 +862,453 @@ mod tests {
 
     #[test]
     fn list_promotes_active_tmux_first() -> Result<()> {
-        let sh = MockShell::default()
-            .with("tmux", &["list-sessions", "-F", "#S"], "b\na\n")
-            .with("tmux", &["display-message", "-p", "-F", "#S"], "b\n");
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", "#S"], "b\na\n")
+            .output("tmux", &["display-message", "-p", "-F", "#S"], "b\n");
         let lines = build_list_lines(&sh, &opts_ascii(true))?;
         assert_eq!(lines[..2], [String::from("[t] b"), String::from("[t] a")]);
         Ok(())
     }
+
+    fn no_server() -> crate::error::NitroError {
+        crate::error::NitroError::command_failed(
+            "tmux",
+            &[],
+            "no server running on /tmp/tmux-1000/default",
+        )
+    }
+
+    #[test]
+    fn list_no_tmux_server_is_empty_even_when_strict() -> Result<()> {
+        let sh = ReplayShell::default()
+            .fail("tmux", &["list-sessions", "-F", "#S"], no_server())
+            .fail("tmux", &["display-message", "-p", "-F", "#S"], no_server())
+            .output("zoxide", &["query", "-ls"], "/z/c\n");
+        let mut opts = opts_ascii(true);
+        opts.strict = true;
+        let lines = build_list_lines(&sh, &opts)?;
+        assert_eq!(lines, vec![String::from("[z] /z/c")]);
+        Ok(())
+    }
+
+    #[test]
+    fn list_missing_tmux_warns_or_fails_when_strict() -> Result<()> {
+        let missing = crate::error::NitroError::MissingProgram("tmux".into());
+        let sh = || {
+            ReplayShell::default()
+                .fail("tmux", &["list-sessions", "-F", "#S"], missing.clone())
+                .output("zoxide", &["query", "-ls"], "/z/c\n")
+        };
+        let lines = build_list_lines(&sh(), &opts_ascii(true))?;
+        assert_eq!(lines, vec![String::from("[z] /z/c")]);
+
+        let mut opts = opts_ascii(true);
+        opts.strict = true;
+        let err = build_list_lines(&sh(), &opts).unwrap_err();
+        assert_eq!(crate::error::find(&err), Some(&missing));
+        Ok(())
+    }
+
+    #[test]
+    fn list_tmux_socket_permission_error_is_not_no_server() {
+        let denied = crate::error::NitroError::command_failed(
+            "tmux",
+            &[],
+            "error connecting to /tmp/tmux-1000/default (Permission denied)",
+        );
+        let sh =
+            ReplayShell::default().fail("tmux", &["list-sessions", "-F", "#S"], denied.clone());
+        let mut opts = opts_ascii(true);
+        opts.include_zox = false;
+        opts.strict = true;
+        let err = build_list_lines(&sh, &opts).unwrap_err();
+        assert_eq!(crate::error::find(&err), Some(&denied));
+    }
+
+    #[test]
+    fn list_broken_zoxide_warns_or_fails_when_strict() -> Result<()> {
+        let broken =
+            crate::error::NitroError::command_failed("zoxide", &["query", "-ls"], "corrupted db");
+        let sh = || {
+            ReplayShell::default()
+                .output("tmux", &["list-sessions", "-F", "#S"], "a\n")
+                .output("tmux", &["display-message", "-p", "-F", "#S"], "\n")
+                .output("tmux", &["list-sessions", "-F", ATTACHED], "")
+                .fail("zoxide", &["query", "-ls"], broken.clone())
+        };
+        let lines = build_list_lines(&sh(), &opts_ascii(true))?;
+        assert_eq!(lines, vec![String::from("[t] a")]);
+
+        let mut opts = opts_ascii(true);
+        opts.strict = true;
+        let err = build_list_lines(&sh(), &opts).unwrap_err();
+        assert_eq!(crate::error::find(&err), Some(&broken));
+        Ok(())
+    }
+
+    #[test]
+    fn list_all_servers_labels_sessions() -> Result<()> {
+        let base = std::env::temp_dir().join(format!("nitro-list-servers-{}", std::process::id()));
+        let dir = base.join("tmux-1000");
+        std::fs::create_dir_all(&dir)?;
+        let _a = std::os::unix::net::UnixListener::bind(dir.join("default"))?;
+        let _b = std::os::unix::net::UnixListener::bind(dir.join("work"))?;
+        let sh = ReplayShell::default()
+            .env("TMUX_TMPDIR", &base.to_string_lossy())
+            .output("id", &["-u"], "1000\n")
+            .output(
+                "tmux",
+                &["-L", "default", "list-sessions", "-F", "#S"],
+                "home\n",
+            )
+            .output(
+                "tmux",
+                &["-L", "work", "list-sessions", "-F", "#S"],
+                "api\nweb\n",
+            )
+            .output("zoxide", &["query", "-ls"], "/src/api\n/src/other\n");
+        let mut opts = opts_ascii(true);
+        opts.all_servers = true;
+        let lines = build_list_lines(&sh, &opts);
+        std::fs::remove_dir_all(&base)?;
+        assert_eq!(
+            lines?,
+            vec![
+                String::from("[t] default:home"),
+                String::from("[t] work:api"),
+                String::from("[t] work:web"),
+                String::from("[z] /src/other"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn list_pins_first_and_dedup_other_sources() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("nitro-list-pins-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("pins"), "/src/api\nnotes\n")?;
+        let sh = ReplayShell::default()
+            .env("NITRO_DATA_DIR", &dir.to_string_lossy())
+            .output("tmux", &["list-sessions", "-F", "#S"], "api\nnotes\nweb\n")
+            .output("tmux", &["display-message", "-p", "-F", "#S"], "\n")
+            .output("tmux", &["list-sessions", "-F", ATTACHED], "")
+            .output(
+                "zoxide",
+                &["query", "-ls"],
+                "/src/api\n/other/notes\n/src/lib\n",
+            );
+        let mut opts = opts_ascii(true);
+        opts.include_pins = true;
+        let lines = build_list_lines(&sh, &opts);
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(
+            lines?,
+            vec![
+                String::from("[p] /src/api"),
+                String::from("[p] notes"),
+                String::from("[t] web"),
+                String::from("[z] /src/lib"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn list_applies_filters_per_source_before_limit() -> Result<()> {
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", "#S"], "_popup\nwork\n")
+            .output("tmux", &["display-message", "-p", "-F", "#S"], "\n")
+            .output("tmux", &["list-sessions", "-F", ATTACHED], "")
+            .output(
+                "zoxide",
+                &["query", "-ls"],
+                "/tmp/x\n/src/app/node_modules\n/src/api\n/src/web\n",
+            );
+        let cfg = crate::config::FilterConfig {
+            tmux: crate::config::RuleConfig {
+                include: vec![],
+                exclude: vec!["_*".into()],
+            },
+            zoxide: crate::config::RuleConfig {
+                include: vec![],
+                exclude: vec!["**/node_modules".into()],
+            },
+        };
+        let mut opts = opts_ascii(true);
+        opts.filters = Filters::new(&sh, &cfg, &["/tmp/**".into()])?;
+        opts.z_limit = Some(1);
+        let lines = build_list_lines(&sh, &opts)?;
+        assert_eq!(
+            lines,
+            vec![String::from("[t] work"), String::from("[z] /src/api")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn list_prunes_missing_zoxide_dirs_before_limit() -> Result<()> {
+        let tmp = std::env::temp_dir();
+        let listing = format!("/nitro/gone/a\n{}\n/nitro/gone/b\n", tmp.display());
+        let sh = ReplayShell::default().output("zoxide", &["query", "-ls"], &listing);
+        let mut opts = opts_ascii(true);
+        opts.include_tmux = false;
+        opts.prune_missing = true;
+        opts.z_limit = Some(1);
+        let lines = build_list_lines(&sh, &opts)?;
+        assert_eq!(lines, vec![format!("[z] {}", tmp.display())]);
+        Ok(())
+    }
+
+    #[test]
+    fn list_min_score_and_show_scores() -> Result<()> {
+        let sh = ReplayShell::default().output(
+            "zoxide",
+            &["query", "-ls"],
+            " 120.0 /src/api\n   0.5 /tmp/once\n  12.3 /src/web\n",
+        );
+        let mut opts = opts_ascii(true);
+        opts.include_tmux = false;
+        opts.min_score = Some(1.0);
+        opts.show_scores = true;
+        let lines = build_list_lines(&sh, &opts)?;
+        assert_eq!(
+            lines,
+            vec![
+                String::from("[z] /src/api [120.0]"),
+                String::from("[z] /src/web [12.3]"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn entries_serialize_to_json() -> Result<()> {
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", "#S"], "web\n")
+            .output("zoxide", &["query", "-ls"], "  4.0 /src/api\n");
+        let entries = build_entries(&sh, &opts_ascii(true))?;
+        let json = serde_json::to_string(&entries)?;
+        assert_eq!(
+            json,
+            r#"[{"source":"tmux","name":"web"},{"source":"zoxide","name":"api","path":"/src/api","score":4.0}]"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn project_types_pick_icons_and_filter() -> Result<()> {
+        let base = std::env::temp_dir().join(format!("nitro-list-types-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        for dir in ["api", "web", "notes"] {
+            std::fs::create_dir_all(base.join(dir))?;
+        }
+        std::fs::write(base.join("api/Cargo.toml"), "")?;
+        std::fs::write(base.join("web/package.json"), "")?;
+        let listing = format!("{0}/api\n{0}/web\n{0}/notes\n", base.display());
+        let sh = || ReplayShell::default().output("zoxide", &["query", "-ls"], &listing);
+
+        let mut opts = opts_icons(true);
+        opts.include_tmux = false;
+        let lines = build_list_lines(&sh(), &opts)?;
+        assert_eq!(
+            lines,
+            vec![
+                format!("\u{e7a8} {}/api", base.display()),
+                format!("\u{e718} {}/web", base.display()),
+                format!("{} {}/notes", ICON_ZOX, base.display()),
+            ]
+        );
+
+        // Plain lines skip detection; --type keeps matches before -z applies
+        let mut opts = opts_ascii(true);
+        opts.include_tmux = false;
+        assert!(
+            build_entries(&sh(), &opts)?
+                .iter()
+                .all(|e| e.project.is_none())
+        );
+        opts.types = vec![ProjectType::Node];
+        opts.z_limit = Some(1);
+        let lines = build_list_lines(&sh(), &opts)?;
+        assert_eq!(lines, vec![format!("[z] {}/web", base.display())]);
+
+        opts.json = true;
+        opts.types.clear();
+        let json = serde_json::to_string(&build_entries(&sh(), &opts)?[0])?;
+        assert!(json.ends_with(r#""project":"rust"}"#), "{}", json);
+        std::fs::remove_dir_all(&base)?;
+        Ok(())
+    }
+
+    #[test]
+    fn delimited_lines_carry_exact_keys() -> Result<()> {
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", "#S"], "my session\n")
+            .output("tmux", &["display-message", "-p", "-F", "#S"], "\n")
+            .output("tmux", &["list-sessions", "-F", ATTACHED], "")
+            .output("zoxide", &["query", "-ls"], "/src/a  b\n");
+        let mut opts = opts_ascii(true);
+        opts.delimited = true;
+        let lines = build_list_lines(&sh, &opts)?;
+        assert_eq!(
+            lines,
+            [
+                "t::my session\t[t] my session",
+                "z:/src/a  b\t[z] /src/a  b"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn long_lines_align_columns_and_fit_width() -> Result<()> {
+        let now = 1_000_000;
+        let info = format!(
+            "web\t/src/web\t3\t1\t{}\t0\nlongname\t/srv/longname\t1\t0\t{}\t0\n",
+            now - 30,
+            now - 3 * 60 * 60
+        );
+        let fmt = "#{session_name}\t#{session_path}\t#{session_windows}\t#{session_attached}\t#{session_activity}\t#{session_last_attached}";
+        let sh = || {
+            ReplayShell::default()
+                .output("tmux", &["list-sessions", "-F", fmt], &info)
+                .output("tmux", &["display-message", "-p", "-F", "#S"], "web\n")
+                .output("zoxide", &["query", "-ls"], "/src/日本語\n")
+        };
+        let mut opts = opts_ascii(true);
+        opts.long = true;
+        opts.now = now;
+        let lines = build_list_lines(&sh(), &opts)?;
+        assert_eq!(
+            lines,
+            [
+                "[t] * web       3w  1 client  30s ago  /src/web",
+                "[t]   longname  1w             3h ago  /srv/longname",
+                "[z]   日本語                           /src/日本語",
+            ]
+        );
+
+        opts.width = Some(46);
+        let lines = build_list_lines(&sh(), &opts)?;
+        assert_eq!(lines[1], "[t]   longname  1w             3h ago  …ngname");
+        assert_eq!(lines[2], "[z]   日本語                           …日本語");
+        assert!(lines.iter().all(|l| l.width() <= 46));
+        Ok(())
+    }
+
+    #[test]
+    fn templates_per_source_with_details_and_branch() -> Result<()> {
+        let fmt = "#{session_name}\t#{session_path}\t#{session_windows}\t#{session_attached}\t#{session_activity}\t#{session_last_attached}";
+        let sh = || {
+            ReplayShell::default()
+                .output(
+                    "tmux",
+                    &["list-sessions", "-F", fmt],
+                    "web\t/src/web\t3\t1\t0\t0\n",
+                )
+                .output("tmux", &["display-message", "-p", "-F", "#S"], "\n")
+                .output("tmux", &["list-sessions", "-F", ATTACHED], "")
+                .output(
+                    "git",
+                    &["-C", "/src/web", "rev-parse", "--abbrev-ref", "HEAD"],
+                    "main\n",
+                )
+                .output("zoxide", &["query", "-ls"], "  2.0 /src/api\n")
+        };
+        let mut opts = opts_ascii(true);
+        opts.templates = Templates::new(
+            &crate::config::TemplateConfig::default(),
+            &[
+                "{source:<6}|{name:<5}|{score}".into(),
+                "tmux={icon} {name:<5} {windows}w {attached}c {branch} {path}".into(),
+            ],
+        )?;
+        let lines = build_list_lines(&sh(), &opts)?;
+        assert_eq!(lines, ["[t] web   3w 1c main /src/web", "zoxide|api  |2.0"]);
+
+        opts.paint = paint(false);
+        opts.icons = true;
+        let lines = build_list_lines(&sh(), &opts)?;
+        assert!(lines[0].starts_with(&format!("\u{1b}[35m{}\u{1b}[0m web", ICON_TMUX)));
+        Ok(())
+    }
+
+    #[test]
+    fn long_lines_embolden_active_and_dim_stale_sessions() -> Result<()> {
+        let now = 30 * 24 * 60 * 60;
+        let info = format!(
+            "web\t/src/web\t1\t1\t{}\t0\nold\t/src/old\t1\t0\t{}\t0\n",
+            now - 60,
+            now - 8 * 24 * 60 * 60
+        );
+        let fmt = "#{session_name}\t#{session_path}\t#{session_windows}\t#{session_attached}\t#{session_activity}\t#{session_last_attached}";
+        let sh = ReplayShell::default()
+            .output("tmux", &["list-sessions", "-F", fmt], &info)
+            .output("tmux", &["display-message", "-p", "-F", "#S"], "web\n");
+        let mut opts = opts_ascii(false);
+        opts.include_zox = false;
+        opts.long = true;
+        opts.now = now;
+        let lines = build_list_lines(&sh, &opts)?;
+        assert!(lines[0].starts_with("\u{1b}[35m[t]\u{1b}[0m * \u{1b}[1mweb\u{1b}[0m  1w"));
+        assert!(lines[1].starts_with("\u{1b}[35m[t]\u{1b}[0m   \u{1b}[2mold\u{1b}[0m  1w"));
+
+        opts.paint = paint(true);
+        assert!(
+            build_list_lines(&sh, &opts)?
+                .iter()
+                .all(|l| !l.contains('\u{1b}'))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        let entries = [
+            Entry {
+                source: Source::Tmux,
+                name: "api".into(),
+                path: None,
+                server: Some("work:2".into()),
+                score: None,
+                session: None,
+                project: None,
+            },
+            Entry {
+                source: Source::Zoxide,
+                name: "odd".into(),
+                path: Some(PathBuf::from("/src/tab\there\\n")),
+                server: None,
+                score: Some(3.0),
+                session: None,
+                project: None,
+            },
+            Entry {
+                source: Source::Pin,
+                name: "notes".into(),
+                path: None,
+                server: None,
+                score: None,
+                session: None,
+                project: None,
+            },
+        ];
+        let keys: Vec<String> = entries.iter().map(Entry::key).collect();
+        assert_eq!(keys[0], "t:work:2:api");
+        assert_eq!(keys[1], "z:/src/tab\\there\\\\n");
+        assert!(keys.iter().all(|k| !k.contains('\t')));
+
+        let k = parse_key(&keys[0]).unwrap();
+        assert_eq!(
+            (k.source, k.server.as_deref(), k.id.as_str()),
+            (Source::Tmux, Some("work:2"), "api")
+        );
+        assert_eq!(parse_key(&keys[1]).unwrap().id, "/src/tab\there\\n");
+        assert_eq!(parse_key(&keys[2]).unwrap().source, Source::Pin);
+        assert_eq!(parse_key("t::api").unwrap().server, None);
+        assert!(parse_key("x:api").is_none());
+        assert!(parse_key("z:").is_none());
+        assert!(parse_key("api").is_none());
+    }
 }
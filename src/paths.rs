@@ -0,0 +1,217 @@
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// Whether a connect-line word starts a path: absolute, `~` or `~user`,
+/// a `$VAR` reference, or explicitly relative (`.`, `..`, `./x`, `../x`).
+pub fn looks_like_path(word: &str) -> bool {
+    word.starts_with('/')
+        || word.starts_with('~')
+        || word.starts_with('$')
+        || word == "."
+        || word == ".."
+        || word.starts_with("./")
+        || word.starts_with("../")
+}
+
+/// Expand a leading `~`/`~user` and `$VAR`/`${VAR}` references, and make a
+/// relative result absolute against the current directory. A path that
+/// already exists as written is taken literally, so directories with a `$`
+/// or leading `~` in their name still work.
+pub fn expand<S: Shell>(sh: &S, raw: &str) -> Result<PathBuf> {
+    if raw.starts_with('/') && Path::new(raw).exists() {
+        return Ok(PathBuf::from(raw));
+    }
+    let s = expand_vars(sh, raw)?;
+    let path = expand_tilde(sh, &s)?;
+    if path.is_absolute() {
+        return Ok(path);
+    }
+    let cwd = std::env::current_dir().context("cannot read the current directory")?;
+    Ok(cwd.join(path))
+}
+
+fn home<S: Shell>(sh: &S) -> Result<PathBuf> {
+    sh.env_var("HOME")
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
+        .or_else(dirs_next::home_dir)
+        .context("cannot find the home directory")
+}
+
+fn expand_tilde<S: Shell>(sh: &S, s: &str) -> Result<PathBuf> {
+    let Some(rest) = s.strip_prefix('~') else {
+        return Ok(PathBuf::from(s));
+    };
+    let (user, tail) = match rest.find('/') {
+        Some(i) => (&rest[..i], rest[i + 1..].trim_start_matches('/')),
+        None => (rest, ""),
+    };
+    let base = if user.is_empty() {
+        home(sh)?
+    } else {
+        user_home(sh, user)?
+    };
+    // joining "" would add a trailing slash
+    Ok(if tail.is_empty() {
+        base
+    } else {
+        base.join(tail)
+    })
+}
+
+/// Home directory of `user`, from `getent passwd`.
+fn user_home<S: Shell>(sh: &S, user: &str) -> Result<PathBuf> {
+    let entry = sh
+        .run("getent", &["passwd", user])
+        .with_context(|| format!("cannot expand ~{}", user))?;
+    entry
+        .lines()
+        .next()
+        .and_then(|l| l.split(':').nth(5))
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
+        .with_context(|| format!("unknown user {}", user))
+}
+
+/// Replace `$NAME` and `${NAME}`; a `$` not followed by a name is kept.
+fn expand_vars<S: Shell>(sh: &S, s: &str) -> Result<String> {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let (name, next) = if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .with_context(|| format!("unterminated ${{ in {}", s))?;
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = after.find(|c| !is_name(c)).unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+        if name.is_empty() {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let value = sh
+            .env_var(name)
+            .with_context(|| format!("${} is not set (in {})", name, s))?;
+        out.push_str(&value);
+        rest = next;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The canonical form of an absolute path: symlinks resolved for the part
+/// that exists, `.` and `..` dropped from the part that does not (yet).
+pub fn normalize(path: &Path) -> PathBuf {
+    if let Ok(real) = path.canonicalize() {
+        return real;
+    }
+    let mut clean = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                clean.pop();
+            }
+            c => clean.push(c),
+        }
+    }
+    let mut existing = clean.as_path();
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(real) = existing.canonicalize() {
+            return missing.iter().rev().fold(real, |p, c| p.join(c));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return clean,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::NitroError;
+    use crate::transcript::ReplayShell;
+
+    #[test]
+    fn path_words() {
+        for w in [
+            "/a", "~", "~/a", "~bob/a", "$HOME/a", ".", "..", "./a", "../a",
+        ] {
+            assert!(looks_like_path(w), "{}", w);
+        }
+        for w in ["api", "a/b", ".config", "[t]"] {
+            assert!(!looks_like_path(w), "{}", w);
+        }
+    }
+
+    #[test]
+    fn expands_tilde_and_variables() -> Result<()> {
+        let sh = ReplayShell::default()
+            .env("HOME", "/home/me")
+            .env("CODE", "/src");
+        assert_eq!(expand(&sh, "~")?, PathBuf::from("/home/me"));
+        assert_eq!(
+            expand(&sh, "~/code/api")?,
+            PathBuf::from("/home/me/code/api")
+        );
+        assert_eq!(expand(&sh, "$CODE/api")?, PathBuf::from("/src/api"));
+        assert_eq!(expand(&sh, "${CODE}x/$/y")?, PathBuf::from("/srcx/$/y"));
+        assert_eq!(expand(&sh, "/nitro/a~b")?, PathBuf::from("/nitro/a~b"));
+        assert!(expand(&sh, "$NITRO_UNSET/api").is_err());
+
+        let sh = ReplayShell::default()
+            .output(
+                "getent",
+                &["passwd", "bob"],
+                "bob:x:1001:1001::/home/bob:/bin/sh\n",
+            )
+            .fail(
+                "getent",
+                &["passwd", "nobody"],
+                NitroError::command_failed("getent", &["passwd", "nobody"], ""),
+            );
+        assert_eq!(expand(&sh, "~bob/api")?, PathBuf::from("/home/bob/api"));
+        assert!(expand(&sh, "~nobody/api").is_err());
+        assert!(sh.unused().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn relative_paths_are_absolute() -> Result<()> {
+        let cwd = std::env::current_dir()?;
+        let sh = ReplayShell::default();
+        assert_eq!(expand(&sh, "./x")?, cwd.join("./x"));
+        assert_eq!(normalize(&expand(&sh, "./x")?), normalize(&cwd).join("x"));
+        assert_eq!(normalize(&expand(&sh, ".")?), normalize(&cwd));
+        Ok(())
+    }
+
+    #[test]
+    fn normalize_resolves_symlinks_and_dots() -> Result<()> {
+        let base = std::env::temp_dir().join(format!("nitro-paths-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(base.join("real"))?;
+        let real = base.join("real").canonicalize()?;
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&real, base.join("link"))?;
+            assert_eq!(normalize(&base.join("link")), real);
+            assert_eq!(normalize(&base.join("link/new/../x")), real.join("x"));
+        }
+        assert_eq!(normalize(&base.join("real/./a/../b")), real.join("b"));
+        std::fs::remove_dir_all(&base)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+use crate::config;
+use crate::connect::{normalize_name, parse_connect_line};
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// A bookmarked list entry, always listed first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pin {
+    Path(PathBuf),
+    Session(String),
+}
+
+impl Pin {
+    /// Session name connecting to this pin would use.
+    pub fn session_name(&self) -> String {
+        match self {
+            Pin::Session(s) => normalize_name(s),
+            Pin::Path(p) => p
+                .file_name()
+                .map(|s| normalize_name(&s.to_string_lossy()))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Text shown in `nitro list` and stored in the pins file.
+    pub fn display(&self) -> String {
+        match self {
+            Pin::Path(p) => p.display().to_string(),
+            Pin::Session(s) => s.clone(),
+        }
+    }
+
+    /// Resolve a `pin`/`unpin` argument: an existing directory (relative
+    /// ones are made absolute), or a `nitro list` line / session name.
+    pub fn from_target(tokens: &[String]) -> Result<Pin> {
+        let line = tokens.join(" ");
+        let line = line.trim();
+        let as_path = Path::new(line);
+        if !line.is_empty() && as_path.is_dir() && !line.starts_with('[') {
+            let abs = as_path
+                .canonicalize()
+                .with_context(|| format!("failed to resolve {}", line))?;
+            return Ok(Pin::Path(abs));
+        }
+        let parsed = parse_connect_line(tokens);
+        if let Some(p) = parsed.path {
+            return Ok(Pin::Path(p));
+        }
+        if parsed.name.is_empty() {
+            return Err(crate::error::NitroError::EmptySessionName.into());
+        }
+        Ok(Pin::Session(parsed.name))
+    }
+
+    fn parse(line: &str) -> Option<Pin> {
+        let line = line.trim();
+        if line.is_empty() {
+            None
+        } else if line.starts_with('/') {
+            Some(Pin::Path(PathBuf::from(line)))
+        } else {
+            Some(Pin::Session(line.to_string()))
+        }
+    }
+}
+
+pub fn pins_path<S: Shell>(sh: &S) -> Option<PathBuf> {
+    config::data_dir(sh).map(|d| d.join("pins"))
+}
+
+/// Pins in the order they were added. No file means no pins.
+pub fn load<S: Shell>(sh: &S) -> Result<Vec<Pin>> {
+    let Some(path) = pins_path(sh) else {
+        return Ok(Vec::new());
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    Ok(text.lines().filter_map(Pin::parse).collect())
+}
+
+fn save<S: Shell>(sh: &S, pins: &[Pin]) -> Result<()> {
+    let path = pins_path(sh).context("no data directory for pins")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let mut text = String::new();
+    for p in pins {
+        text.push_str(&p.display());
+        text.push('\n');
+    }
+    std::fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Add a pin; returns false if it was already pinned.
+pub fn add<S: Shell>(sh: &S, pin: Pin) -> Result<bool> {
+    let mut pins = load(sh)?;
+    if pins.contains(&pin) {
+        return Ok(false);
+    }
+    pins.push(pin);
+    save(sh, &pins)?;
+    Ok(true)
+}
+
+/// Remove a pin; returns false if it was not pinned.
+pub fn remove<S: Shell>(sh: &S, pin: &Pin) -> Result<bool> {
+    let mut pins = load(sh)?;
+    let before = pins.len();
+    pins.retain(|p| p != pin);
+    if pins.len() == before {
+        return Ok(false);
+    }
+    save(sh, &pins)?;
+    Ok(true)
+}
+
+pub fn run_pin<S: Shell>(sh: &S, target: &[String]) -> Result<()> {
+    let pin = Pin::from_target(target)?;
+    if !add(sh, pin.clone())? {
+        eprintln!("already pinned: {}", pin.display());
+    }
+    Ok(())
+}
+
+pub fn run_unpin<S: Shell>(sh: &S, target: &[String]) -> Result<()> {
+    let pin = Pin::from_target(target)?;
+    if !remove(sh, &pin)? {
+        anyhow::bail!("not pinned: {}", pin.display());
+    }
+    Ok(())
+}
+
+pub fn run_pins<S: Shell>(sh: &S) -> Result<()> {
+    for p in load(sh)? {
+        println!("{}", p.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::ReplayShell;
+
+    fn data_shell(tag: &str) -> (ReplayShell, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("nitro-pins-{}-{}", tag, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let sh = ReplayShell::default().env("NITRO_DATA_DIR", &dir.to_string_lossy());
+        (sh, dir)
+    }
+
+    fn tokens(s: &[&str]) -> Vec<String> {
+        s.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn target_resolution() -> Result<()> {
+        assert_eq!(
+            Pin::from_target(&tokens(&["[z]", "/no/such/api"]))?,
+            Pin::Path("/no/such/api".into())
+        );
+        assert_eq!(
+            Pin::from_target(&tokens(&["[t]", "my", "web"]))?,
+            Pin::Session("my-web".into())
+        );
+        let tmp = std::env::temp_dir().canonicalize()?;
+        assert_eq!(
+            Pin::from_target(&tokens(&[&tmp.to_string_lossy()]))?,
+            Pin::Path(tmp)
+        );
+        assert!(Pin::from_target(&tokens(&["[p]"])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn add_remove_round_trip() -> Result<()> {
+        let (sh, dir) = data_shell("rt");
+        assert!(load(&sh)?.is_empty());
+        assert!(add(&sh, Pin::Path("/src/api".into()))?);
+        assert!(add(&sh, Pin::Session("notes".into()))?);
+        assert!(!add(&sh, Pin::Session("notes".into()))?);
+        assert_eq!(
+            load(&sh)?,
+            vec![Pin::Path("/src/api".into()), Pin::Session("notes".into())]
+        );
+        assert!(remove(&sh, &Pin::Path("/src/api".into()))?);
+        assert!(!remove(&sh, &Pin::Path("/src/api".into()))?);
+        assert_eq!(load(&sh)?, vec![Pin::Session("notes".into())]);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn session_name_from_pin() {
+        assert_eq!(Pin::Path("/src/my api".into()).session_name(), "my-api");
+        assert_eq!(Pin::Session("notes".into()).session_name(), "notes");
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{Result, bail};
+use serde::Serialize;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The kind of project a directory holds, told by its marker files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ProjectType {
+    /// Cargo
+    Rust,
+    /// Go modules
+    Go,
+    /// package.json
+    Node,
+    /// pyproject.toml, setup.py or requirements.txt
+    Python,
+    /// Gemfile
+    Ruby,
+    /// Maven or Gradle
+    Java,
+    /// Mix
+    Elixir,
+    /// build.zig
+    Zig,
+    /// CMake or Meson
+    Cpp,
+    /// A flake, default.nix or shell.nix
+    Nix,
+}
+
+/// Every type, in the order `--type` help lists them.
+pub const ALL: [ProjectType; 10] = [
+    ProjectType::Rust,
+    ProjectType::Go,
+    ProjectType::Node,
+    ProjectType::Python,
+    ProjectType::Ruby,
+    ProjectType::Java,
+    ProjectType::Elixir,
+    ProjectType::Zig,
+    ProjectType::Cpp,
+    ProjectType::Nix,
+];
+
+/// Marker files, checked in order. Nix comes last: a flake next to a
+/// `Cargo.toml` usually just packages the crate.
+const MARKERS: &[(&str, ProjectType)] = &[
+    ("Cargo.toml", ProjectType::Rust),
+    ("go.mod", ProjectType::Go),
+    ("package.json", ProjectType::Node),
+    ("pyproject.toml", ProjectType::Python),
+    ("setup.py", ProjectType::Python),
+    ("requirements.txt", ProjectType::Python),
+    ("Gemfile", ProjectType::Ruby),
+    ("pom.xml", ProjectType::Java),
+    ("build.gradle", ProjectType::Java),
+    ("build.gradle.kts", ProjectType::Java),
+    ("mix.exs", ProjectType::Elixir),
+    ("build.zig", ProjectType::Zig),
+    ("CMakeLists.txt", ProjectType::Cpp),
+    ("meson.build", ProjectType::Cpp),
+    ("flake.nix", ProjectType::Nix),
+    ("default.nix", ProjectType::Nix),
+    ("shell.nix", ProjectType::Nix),
+];
+
+impl ProjectType {
+    /// The type of the first marker file found in `dir`, if any.
+    pub fn detect(dir: &Path) -> Option<ProjectType> {
+        MARKERS
+            .iter()
+            .find(|(file, _)| dir.join(file).is_file())
+            .map(|(_, t)| *t)
+    }
+
+    /// Lowercase name, as taken by `--type` and written in JSON.
+    pub fn name(self) -> &'static str {
+        match self {
+            ProjectType::Rust => "rust",
+            ProjectType::Go => "go",
+            ProjectType::Node => "node",
+            ProjectType::Python => "python",
+            ProjectType::Ruby => "ruby",
+            ProjectType::Java => "java",
+            ProjectType::Elixir => "elixir",
+            ProjectType::Zig => "zig",
+            ProjectType::Cpp => "cpp",
+            ProjectType::Nix => "nix",
+        }
+    }
+
+    /// Nerd Font glyph shown with `--icons`.
+    pub fn icon(self) -> &'static str {
+        match self {
+            ProjectType::Rust => "\u{e7a8}",
+            ProjectType::Go => "\u{e627}",
+            ProjectType::Node => "\u{e718}",
+            ProjectType::Python => "\u{e73c}",
+            ProjectType::Ruby => "\u{e739}",
+            ProjectType::Java => "\u{e738}",
+            ProjectType::Elixir => "\u{e62d}",
+            ProjectType::Zig => "\u{e6a9}",
+            ProjectType::Cpp => "\u{e61d}",
+            ProjectType::Nix => "\u{f313}",
+        }
+    }
+}
+
+impl FromStr for ProjectType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ProjectType> {
+        match ALL.iter().find(|t| t.name() == s) {
+            Some(t) => Ok(*t),
+            None => {
+                let names: Vec<&str> = ALL.iter().map(|t| t.name()).collect();
+                bail!("unknown project type {:?} (use {})", s, names.join(", "))
+            }
+        }
+    }
+}
+
+/// `--type` values, so clap rejects unknown types as usage errors.
+impl clap::ValueEnum for ProjectType {
+    fn value_variants<'a>() -> &'a [ProjectType] {
+        &ALL
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_by_marker_in_order() -> Result<()> {
+        let base = std::env::temp_dir().join(format!("nitro-project-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base)?;
+        assert_eq!(ProjectType::detect(&base), None);
+        std::fs::write(base.join("flake.nix"), "")?;
+        assert_eq!(ProjectType::detect(&base), Some(ProjectType::Nix));
+        std::fs::create_dir(base.join("go.mod"))?; // a directory is no marker
+        assert_eq!(ProjectType::detect(&base), Some(ProjectType::Nix));
+        std::fs::write(base.join("Cargo.toml"), "")?;
+        assert_eq!(ProjectType::detect(&base), Some(ProjectType::Rust));
+        std::fs::remove_dir_all(&base)?;
+        Ok(())
+    }
+
+    #[test]
+    fn parses_names() -> Result<()> {
+        for t in ALL {
+            assert_eq!(t.name().parse::<ProjectType>()?, t);
+        }
+        assert!("cobol".parse::<ProjectType>().is_err());
+        Ok(())
+    }
+}
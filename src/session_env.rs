@@ -0,0 +1,143 @@
+use crate::config::EnvConfig;
+use crate::error;
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Parse `KEY=value` lines. Blank lines, `#` comments and an `export `
+/// prefix are ignored; matching single or double quotes are stripped. There
+/// is no variable interpolation.
+pub fn parse_dotenv(text: &str) -> Vec<(String, String)> {
+    let mut vars = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            continue;
+        }
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)))
+            .unwrap_or(value);
+        vars.push((key.to_string(), value.to_string()));
+    }
+    vars
+}
+
+/// Variables direnv would export in `dir`, via `direnv export json`.
+/// Variables direnv would unset (null) are skipped.
+pub fn direnv_export<S: Shell>(sh: &S, dir: &Path) -> Result<Vec<(String, String)>> {
+    let dir = dir.to_string_lossy();
+    let out = sh.run("sh", &["-c", "cd \"$1\" && direnv export json", "sh", &dir])?;
+    if out.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let map: HashMap<String, Option<String>> =
+        serde_json::from_str(&out).context("unexpected direnv output")?;
+    let mut vars: Vec<(String, String)> = map
+        .into_iter()
+        .filter_map(|(k, v)| Some((k, v?)))
+        // direnv's own bookkeeping is meaningless inside tmux panes
+        .filter(|(k, _)| !k.starts_with("DIRENV_"))
+        .collect();
+    vars.sort();
+    Ok(vars)
+}
+
+/// Environment for a new session in `dir`: the dotenv file first, then
+/// direnv (later values win). Failures are reported and skipped so a broken
+/// `.env` never blocks creating the session.
+pub fn collect<S: Shell>(sh: &S, cfg: &EnvConfig, dir: &Path) -> Vec<(String, String)> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    if cfg.dotenv {
+        let file = dir.join(cfg.dotenv_file.as_deref().unwrap_or(".env"));
+        match std::fs::read_to_string(&file) {
+            Ok(text) => vars.extend(parse_dotenv(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => error::warn(
+                &anyhow::Error::new(e).context(format!("failed to read {}", file.display())),
+            ),
+        }
+    }
+    if cfg.direnv {
+        match direnv_export(sh, dir) {
+            Ok(v) => vars.extend(v),
+            Err(e) => error::warn(&e.context("direnv export failed")),
+        }
+    }
+    // Keep the last value per key, in first-seen order
+    let mut merged: Vec<(String, String)> = Vec::new();
+    for (k, v) in vars {
+        match merged.iter_mut().find(|(mk, _)| *mk == k) {
+            Some(slot) => slot.1 = v,
+            None => merged.push((k, v)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::transcript::ReplayShell;
+
+    /// A shell whose `direnv export json` in `dir` prints `out`.
+    fn direnv(dir: &Path, out: &str) -> ReplayShell {
+        let dir = dir.to_string_lossy();
+        let script = "cd \"$1\" && direnv export json";
+        ReplayShell::default().output("sh", &["-c", script, "sh", &dir], out)
+    }
+
+    fn pairs(v: &[(&str, &str)]) -> Vec<(String, String)> {
+        v.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn dotenv_parsing() {
+        let text = "# comment\n\nA=1\nexport B=\"two words\"\nC='x=y'\nbad line\n D = 4 \n";
+        assert_eq!(
+            parse_dotenv(text),
+            pairs(&[("A", "1"), ("B", "two words"), ("C", "x=y"), ("D", "4")])
+        );
+    }
+
+    #[test]
+    fn direnv_json_skips_unset_and_internal() -> Result<()> {
+        let p = Path::new("/p");
+        let sh = direnv(p, r#"{"B":"2","A":"1","GONE":null,"DIRENV_DIFF":"x"}"#);
+        assert_eq!(direnv_export(&sh, p)?, pairs(&[("A", "1"), ("B", "2")]));
+        assert!(direnv_export(&direnv(p, ""), p)?.is_empty());
+        assert!(direnv_export(&direnv(p, "not json"), p).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn collect_merges_dotenv_then_direnv() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("nitro-env-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(".env"), "A=dot\nB=dot\n")?;
+        let cfg = EnvConfig {
+            dotenv: true,
+            direnv: true,
+            dotenv_file: None,
+        };
+        let vars = collect(&direnv(&dir, r#"{"B":"direnv"}"#), &cfg, &dir);
+        let none = collect(&ReplayShell::default(), &EnvConfig::default(), &dir);
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(vars, pairs(&[("A", "dot"), ("B", "direnv")]));
+        assert!(none.is_empty());
+        Ok(())
+    }
+}
@@ -1,17 +1,66 @@
-use anyhow::{Context, Result};
+use crate::error::NitroError;
+use anyhow::Result;
+use std::io::{IsTerminal, Write};
 use std::process::{Command, Stdio};
 
+/// Every external command and environment lookup goes through this trait,
+/// so callers can substitute a fake for tests or scripted runs.
 pub trait Shell {
+    /// Run a command and return its stdout; a non-zero exit is an error
     fn run(&self, program: &str, args: &[&str]) -> Result<String>;
+    /// Run a command and report whether it exited successfully
     fn run_status(&self, program: &str, args: &[&str]) -> Result<bool>;
     /// Run a command that requires a real TTY (stdin/stdout/stderr inherited)
     fn run_tty(&self, program: &str, args: &[&str]) -> Result<()>;
+    /// Look up an environment variable; `None` when unset
     fn env_var(&self, key: &str) -> Option<String>;
+    /// Run an interactive picker such as fzf: `input` goes to its stdin, its
+    /// stdout is returned, and it draws on the terminal. Exit status 1 or
+    /// 130 (nothing picked, cancelled) gives empty output. Fakes answer it
+    /// like `run`.
+    fn run_picker(&self, program: &str, args: &[&str], _input: &str) -> Result<String> {
+        self.run(program, args)
+    }
+    /// Run a command in the foreground: its output goes straight to the
+    /// terminal and it reads the terminal when there is one, so it can show
+    /// progress and prompt. A non-zero exit is an error. Fakes answer it
+    /// like `run`.
+    fn run_interactive(&self, program: &str, args: &[&str]) -> Result<()> {
+        self.run(program, args).map(|_| ())
+    }
+    /// Ask a yes/no question on the terminal. Non-interactive shells answer no.
+    fn confirm(&self, _prompt: &str) -> bool {
+        false
+    }
+    /// Whether `run_tty` can reach a terminal, checked before work that
+    /// would be wasted if the attach at the end fails. Fakes say yes.
+    fn has_terminal(&self) -> bool {
+        true
+    }
 }
 
+/// Runs real processes and reads the process environment.
 #[derive(Debug, Default, Clone)]
 pub struct RealShell;
 
+/// The controlling terminal, for when stdin has been used up by a pipe.
+fn open_tty() -> Option<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()
+}
+
+/// Map a spawn failure to `MissingProgram` when the binary is absent.
+fn spawn_error(program: &str, err: std::io::Error) -> anyhow::Error {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        NitroError::MissingProgram(program.to_string()).into()
+    } else {
+        anyhow::Error::new(err).context(format!("failed to spawn {}", program))
+    }
+}
+
 impl Shell for RealShell {
     fn run(&self, program: &str, args: &[&str]) -> Result<String> {
         let output = Command::new(program)
@@ -19,10 +68,10 @@ impl Shell for RealShell {
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .output()
-            .with_context(|| format!("failed to spawn {}", program))?;
+            .map_err(|e| spawn_error(program, e))?;
         if !output.status.success() {
             let err = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("{} {:?} failed: {}", program, args, err.trim());
+            return Err(NitroError::command_failed(program, args, err.trim()).into());
         }
         Ok(String::from_utf8_lossy(&output.stdout).to_string())
     }
@@ -33,20 +82,29 @@ impl Shell for RealShell {
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .status()
-            .with_context(|| format!("failed to spawn {}", program))?;
+            .map_err(|e| spawn_error(program, e))?;
         Ok(status.success())
     }
 
     fn run_tty(&self, program: &str, args: &[&str]) -> Result<()> {
+        // `... | nitro connect` reads stdin to the end; attach on /dev/tty
+        let stdin = if std::io::stdin().is_terminal() {
+            Stdio::inherit()
+        } else {
+            open_tty()
+                .map(Stdio::from)
+                .ok_or(NitroError::NotATerminal)?
+        };
         let status = Command::new(program)
             .args(args)
-            .stdin(Stdio::inherit())
+            .stdin(stdin)
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
             .status()
-            .with_context(|| format!("failed to spawn {}", program))?;
+            .map_err(|e| spawn_error(program, e))?;
         if !status.success() {
-            anyhow::bail!("{} {:?} failed with status {}", program, args, status);
+            let detail = format!("exited with status {}", status);
+            return Err(NitroError::command_failed(program, args, detail).into());
         }
         Ok(())
     }
@@ -54,4 +112,86 @@ impl Shell for RealShell {
     fn env_var(&self, key: &str) -> Option<String> {
         std::env::var(key).ok()
     }
+
+    fn run_interactive(&self, program: &str, args: &[&str]) -> Result<()> {
+        let stdin = if std::io::stdin().is_terminal() {
+            Stdio::inherit()
+        } else {
+            open_tty().map(Stdio::from).unwrap_or_else(Stdio::null)
+        };
+        let status = Command::new(program)
+            .args(args)
+            .stdin(stdin)
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .map_err(|e| spawn_error(program, e))?;
+        if !status.success() {
+            let detail = format!("exited with status {}", status);
+            return Err(NitroError::command_failed(program, args, detail).into());
+        }
+        Ok(())
+    }
+
+    fn run_picker(&self, program: &str, args: &[&str], input: &str) -> Result<String> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| spawn_error(program, e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // The picker may exit before reading everything
+            let _ = stdin.write_all(input.as_bytes());
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|e| spawn_error(program, e))?;
+        match output.status.code() {
+            Some(0) => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+            Some(1 | 130) => Ok(String::new()),
+            _ => {
+                let detail = format!("exited with status {}", output.status);
+                Err(NitroError::command_failed(program, args, detail).into())
+            }
+        }
+    }
+
+    fn has_terminal(&self) -> bool {
+        std::io::stdin().is_terminal() || open_tty().is_some()
+    }
+
+    fn confirm(&self, prompt: &str) -> bool {
+        if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+            return false;
+        }
+        eprint!("{} [y/N] ", prompt);
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).is_err() {
+            return false;
+        }
+        matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+
+    #[test]
+    fn missing_binary_maps_to_missing_program() {
+        let err = RealShell.run("nitro-test-no-such-binary", &[]).unwrap_err();
+        assert_eq!(
+            error::find(&err),
+            Some(&NitroError::MissingProgram(
+                "nitro-test-no-such-binary".into()
+            ))
+        );
+        let err = RealShell
+            .run_status("nitro-test-no-such-binary", &[])
+            .unwrap_err();
+        assert_eq!(error::exit_code(&err), error::EXIT_MISSING_PROGRAM);
+    }
 }
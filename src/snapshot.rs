@@ -0,0 +1,526 @@
+use crate::config;
+use crate::error;
+use crate::shell::Shell;
+use crate::tmux;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Format version written by `nitro save`. Bump it when a change would make
+/// older nitro versions misread the file.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Snapshot {
+    pub version: u32,
+    pub sessions: Vec<SessionSnap>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SessionSnap {
+    pub name: String,
+    pub path: String,
+    pub windows: Vec<WindowSnap>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct WindowSnap {
+    pub index: u32,
+    pub name: String,
+    pub layout: String,
+    pub active: bool,
+    pub panes: Vec<PaneSnap>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PaneSnap {
+    pub index: u32,
+    pub path: String,
+    /// Foreground command line, only recorded with `nitro save --commands`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    pub active: bool,
+}
+
+pub fn default_path<S: Shell>(sh: &S) -> Option<PathBuf> {
+    config::data_dir(sh).map(|d| d.join("snapshot.json"))
+}
+
+/// Every process as pid -> (terminal foreground process group, command line).
+fn process_table<S: Shell>(sh: &S) -> Result<HashMap<u32, (u32, String)>> {
+    let out = sh.run(
+        "ps",
+        &["-A", "-ww", "-o", "pid=", "-o", "tpgid=", "-o", "args="],
+    )?;
+    Ok(out
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start();
+            let (pid, rest) = line.split_once(char::is_whitespace)?;
+            let rest = rest.trim_start();
+            let (tpgid, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            // tpgid is -1 without a controlling terminal
+            Some((
+                pid.parse().ok()?,
+                (tpgid.parse().unwrap_or(0), args.trim().to_string()),
+            ))
+        })
+        .collect())
+}
+
+/// The arguments of a process from `/proc/<pid>/cmdline`; `None` where
+/// there is no procfs.
+fn proc_argv(pid: u32) -> Option<Vec<String>> {
+    let raw = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let argv: Vec<String> = raw
+        .split(|b| *b == 0)
+        .map(|w| String::from_utf8_lossy(w).into_owned())
+        .collect();
+    // cmdline ends with a NUL, leaving an empty last word
+    let argv = match argv.split_last() {
+        Some((last, rest)) if last.is_empty() => rest.to_vec(),
+        _ => argv,
+    };
+    Some(argv).filter(|a| !a.is_empty())
+}
+
+/// Argument words joined into a line a shell splits back into the same
+/// words.
+fn quote_argv(argv: &[String]) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c);
+    argv.iter()
+        .map(|w| {
+            if !w.is_empty() && w.chars().all(plain) {
+                w.clone()
+            } else {
+                tmux::shell_quote(w)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Command line of the job in the foreground of the pane whose shell is
+/// `pid`: the leader of the terminal's foreground process group. Its
+/// arguments come quoted from `argv` when it knows them; `ps` output has
+/// lost the quoting and is only a fallback.
+fn foreground_command(
+    procs: &HashMap<u32, (u32, String)>,
+    pid: u32,
+    argv: impl Fn(u32) -> Option<Vec<String>>,
+) -> Option<String> {
+    let (tpgid, _) = procs.get(&pid)?;
+    if *tpgid == 0 || *tpgid == pid {
+        return None;
+    }
+    let (_, args) = procs.get(tpgid)?;
+    argv(*tpgid)
+        .map(|a| quote_argv(&a))
+        .or_else(|| Some(args.clone()))
+        .filter(|a| !a.is_empty())
+}
+
+/// Snapshot every session on the server. Pane commands are kept only when
+/// `commands` is set, and never for plain shells.
+pub fn capture<S: Shell>(sh: &S, commands: bool) -> Result<Snapshot> {
+    capture_with(sh, commands, proc_argv)
+}
+
+fn capture_with<S: Shell>(
+    sh: &S,
+    commands: bool,
+    argv: impl Fn(u32) -> Option<Vec<String>>,
+) -> Result<Snapshot> {
+    let windows = tmux::list_all_windows(sh)?;
+    let panes = tmux::list_all_panes(sh)?;
+    // tmux only knows the program name, so ask ps for the arguments
+    let procs = if commands {
+        process_table(sh)
+            .context("cannot record pane commands")
+            .unwrap_or_else(|e| {
+                error::warn(&e);
+                HashMap::new()
+            })
+    } else {
+        HashMap::new()
+    };
+    let mut sessions = Vec::new();
+    for (name, path) in tmux::list_session_paths(sh)? {
+        let mut wins: Vec<WindowSnap> = windows
+            .iter()
+            .filter(|w| w.session == name)
+            .map(|w| {
+                let mut panes: Vec<PaneSnap> = panes
+                    .iter()
+                    .filter(|p| p.session == name && p.window_index == w.index)
+                    .map(|p| PaneSnap {
+                        index: p.index,
+                        path: p.path.clone(),
+                        command: Some(&p.command)
+                            .filter(|c| !c.is_empty() && !tmux::is_shell_command(c))
+                            .and_then(|_| foreground_command(&procs, p.pid, &argv)),
+                        active: p.active,
+                    })
+                    .collect();
+                panes.sort_by_key(|p| p.index);
+                WindowSnap {
+                    index: w.index,
+                    name: w.name.clone(),
+                    layout: w.layout.clone(),
+                    active: w.active,
+                    panes,
+                }
+            })
+            .collect();
+        wins.sort_by_key(|w| w.index);
+        sessions.push(SessionSnap {
+            name,
+            path,
+            windows: wins,
+        });
+    }
+    Ok(Snapshot {
+        version: VERSION,
+        sessions,
+    })
+}
+
+pub fn parse(text: &str) -> Result<Snapshot> {
+    #[derive(Deserialize)]
+    struct Header {
+        version: u32,
+    }
+    let header: Header = serde_json::from_str(text).context("not a nitro snapshot")?;
+    if header.version > VERSION {
+        anyhow::bail!(
+            "snapshot version {} is newer than this nitro supports ({})",
+            header.version,
+            VERSION
+        );
+    }
+    serde_json::from_str(text).context("malformed snapshot")
+}
+
+fn existing_dir(candidates: &[&str], fallback: &str) -> String {
+    candidates
+        .iter()
+        .find(|d| !d.is_empty() && Path::new(d).is_dir())
+        .map(|d| d.to_string())
+        .unwrap_or_else(|| fallback.to_string())
+}
+
+/// Recreate one saved session. Directories that no longer exist fall back to
+/// the session directory, then `home`. Saved commands are typed into their
+/// panes but not run: a pipeline or compound command was saved as its first
+/// program only, so the line is left for the user to check and submit.
+pub fn restore_session<S: Shell>(sh: &S, snap: &SessionSnap, home: &str) -> Result<()> {
+    let session_dir = existing_dir(&[&snap.path], home);
+    let pane_dir = |p: &PaneSnap| existing_dir(&[&p.path], &session_dir);
+    let first_dir = snap
+        .windows
+        .first()
+        .and_then(|w| w.panes.first())
+        .map(pane_dir)
+        .unwrap_or_else(|| session_dir.clone());
+    tmux::create_session(sh, &snap.name, &first_dir, &[])?;
+    let (first_window, first_pane) = tmux::first_window_and_pane(sh, &snap.name)?;
+
+    let mut active_window = None;
+    for (wi, w) in snap.windows.iter().enumerate() {
+        // The first pane comes with the window; the rest are splits
+        let (window, pane) = if wi == 0 {
+            tmux::rename_window(sh, &first_window, &w.name)?;
+            (first_window.clone(), first_pane.clone())
+        } else {
+            let dir = w
+                .panes
+                .first()
+                .map(pane_dir)
+                .unwrap_or_else(|| session_dir.clone());
+            let window = tmux::new_window(sh, &snap.name, &w.name, &dir)?;
+            let pane = tmux::first_window_and_pane(sh, &window)?.1;
+            (window, pane)
+        };
+        let mut ids = vec![pane];
+        for p in w.panes.iter().skip(1) {
+            ids.push(tmux::split_window(sh, &window, &pane_dir(p))?);
+        }
+        if !w.layout.is_empty() {
+            tmux::select_layout(sh, &window, &w.layout)?;
+        }
+        for (p, id) in w.panes.iter().zip(&ids) {
+            if let Some(cmd) = &p.command {
+                tmux::type_text(sh, id, cmd)?;
+            }
+            if p.active {
+                tmux::select_pane(sh, id)?;
+            }
+        }
+        if w.active {
+            active_window = Some(window);
+        }
+    }
+    if let Some(w) = active_window {
+        tmux::select_window(sh, &w)?;
+    }
+    Ok(())
+}
+
+pub fn run_save<S: Shell>(sh: &S, file: Option<&Path>, commands: bool) -> Result<()> {
+    let path = match file {
+        Some(f) => f.to_path_buf(),
+        None => default_path(sh).context("no data directory for the snapshot")?,
+    };
+    let snap = capture(sh, commands)?;
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let text = serde_json::to_string_pretty(&snap)?;
+    std::fs::write(&path, text + "\n")
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    eprintln!(
+        "saved {} session(s) to {}",
+        snap.sessions.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Recreate every saved session that is not already running.
+pub fn run_restore<S: Shell>(sh: &S, file: Option<&Path>) -> Result<()> {
+    let path = match file {
+        Some(f) => f.to_path_buf(),
+        None => default_path(sh).context("no data directory for the snapshot")?,
+    };
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let snap = parse(&text).with_context(|| format!("cannot restore {}", path.display()))?;
+    let home = dirs_next::home_dir()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|| "/".to_string());
+    for s in &snap.sessions {
+        if tmux::has_session(sh, &s.name)? {
+            eprintln!("skipping {}: already running", s.name);
+            continue;
+        }
+        restore_session(sh, s, &home).with_context(|| format!("failed to restore {}", s.name))?;
+        println!("{}", s.name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::{Call, ReplayShell};
+
+    const WINDOWS: &str =
+        "#{session_name}\t#{window_index}\t#{window_name}\t#{window_layout}\t#{window_active}";
+    const PANES: &str = "#{session_name}\t#{window_index}\t#{pane_index}\t#{pane_current_path}\t#{pane_current_command}\t#{pane_active}\t#{pane_pid}";
+    const PS: [&str; 8] = ["-A", "-ww", "-o", "pid=", "-o", "tpgid=", "-o", "args="];
+
+    fn listing() -> ReplayShell {
+        ReplayShell::default()
+            .output(
+                "tmux",
+                &["list-sessions", "-F", "#{session_name}\t#{session_path}"],
+                "api\t/src/api\n",
+            )
+            .output(
+                "tmux",
+                &["list-windows", "-a", "-F", WINDOWS],
+                "api\t1\tedit\tlayout-a\t0\napi\t2\tlogs\tlayout-b\t1\n",
+            )
+            .output(
+                "tmux",
+                &["list-panes", "-a", "-F", PANES],
+                "api\t1\t0\t/src/api\tnvim\t1\t100\napi\t2\t1\t/var/log\ttail\t0\t200\napi\t2\t0\t/src/api\t-zsh\t1\t300\n",
+            )
+            .output(
+                "ps",
+                &PS,
+                "  100   101 -zsh\n  101   101 nvim -p a.rs b.rs\n  200   201 -zsh\n  201   201 tail -f app.log\n  202   201 grep error\n  300   300 -zsh\n    1    -1 /sbin/init splash\n",
+            )
+    }
+
+    #[test]
+    fn capture_groups_windows_and_panes() -> Result<()> {
+        let snap = capture_with(&listing(), true, |_| None)?;
+        assert_eq!(snap.version, VERSION);
+        let s = &snap.sessions[0];
+        assert_eq!((s.name.as_str(), s.path.as_str()), ("api", "/src/api"));
+        assert_eq!(s.windows.len(), 2);
+        let logs = &s.windows[1];
+        assert_eq!((logs.name.as_str(), logs.active), ("logs", true));
+        assert_eq!(logs.panes[0].index, 0);
+        assert_eq!(logs.panes[0].command, None); // shells are not replayed
+        assert_eq!(logs.panes[1].command.as_deref(), Some("tail -f app.log"));
+        assert_eq!(
+            s.windows[0].panes[0].command.as_deref(),
+            Some("nvim -p a.rs b.rs")
+        );
+
+        let sh = listing();
+        let plain = capture_with(&sh, false, |_| None)?;
+        assert!(plain.sessions[0].windows[0].panes[0].command.is_none());
+        let ps = Call::Run {
+            program: "ps".into(),
+            args: PS.map(String::from).to_vec(),
+        };
+        assert_eq!(sh.unused(), [ps]);
+        Ok(())
+    }
+
+    #[test]
+    fn capture_quotes_arguments_from_argv() -> Result<()> {
+        let argv = |pid| match pid {
+            101 => Some(
+                ["git", "commit", "-m", "fix bug"]
+                    .map(String::from)
+                    .to_vec(),
+            ),
+            201 => Some(
+                ["rm", "-rf", "/tmp/my dir", "it's"]
+                    .map(String::from)
+                    .to_vec(),
+            ),
+            _ => None,
+        };
+        let snap = capture_with(&listing(), true, argv)?;
+        let windows = &snap.sessions[0].windows;
+        assert_eq!(
+            windows[0].panes[0].command.as_deref(),
+            Some("git commit -m 'fix bug'")
+        );
+        assert_eq!(
+            windows[1].panes[1].command.as_deref(),
+            Some("rm -rf '/tmp/my dir' 'it'\\''s'")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_round_trips_and_rejects_newer_versions() -> Result<()> {
+        let snap = capture(&listing(), false)?;
+        let text = serde_json::to_string(&snap)?;
+        assert!(!text.contains("command"));
+        assert_eq!(parse(&text)?, snap);
+        let newer = r#"{"version": 99, "sessions": []}"#;
+        assert!(parse(newer).unwrap_err().to_string().contains("newer"));
+        assert!(parse("[]").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn restore_rebuilds_windows_panes_and_focus() -> Result<()> {
+        let tmp = std::env::temp_dir().to_string_lossy().to_string();
+        let snap = SessionSnap {
+            name: "api".into(),
+            path: tmp.clone(),
+            windows: vec![
+                WindowSnap {
+                    index: 1,
+                    name: "edit".into(),
+                    layout: "L1".into(),
+                    active: false,
+                    panes: vec![PaneSnap {
+                        index: 0,
+                        path: "/nitro/gone".into(),
+                        command: Some("nvim".into()),
+                        active: true,
+                    }],
+                },
+                WindowSnap {
+                    index: 2,
+                    name: "logs".into(),
+                    layout: "L2".into(),
+                    active: true,
+                    panes: vec![
+                        PaneSnap {
+                            index: 0,
+                            path: tmp.clone(),
+                            command: None,
+                            active: false,
+                        },
+                        PaneSnap {
+                            index: 1,
+                            path: tmp.clone(),
+                            command: None,
+                            active: true,
+                        },
+                    ],
+                },
+            ],
+        };
+        let layout = ["-F", "#{window_id}\t#{pane_id}"];
+        let script: Vec<(Vec<&str>, &str)> = vec![
+            (vec!["new-session", "-ds", "api", "-c", &tmp], ""),
+            (
+                [&["list-panes", "-t", "api"][..], &layout].concat(),
+                "@1\t%1\n",
+            ),
+            (vec!["rename-window", "-t", "@1", "edit"], ""),
+            (vec!["select-layout", "-t", "@1", "L1"], ""),
+            (vec!["send-keys", "-t", "%1", "-l", "nvim"], ""),
+            (vec!["select-pane", "-t", "%1"], ""),
+            (
+                vec![
+                    "new-window",
+                    "-d",
+                    "-P",
+                    "-F",
+                    "#{window_id}",
+                    "-t",
+                    "api:",
+                    "-n",
+                    "logs",
+                    "-c",
+                    &tmp,
+                ],
+                "@2\n",
+            ),
+            (
+                [&["list-panes", "-t", "@2"][..], &layout].concat(),
+                "@2\t%2\n",
+            ),
+            (
+                vec![
+                    "split-window",
+                    "-d",
+                    "-P",
+                    "-F",
+                    "#{pane_id}",
+                    "-t",
+                    "@2",
+                    "-c",
+                    &tmp,
+                ],
+                "%3\n",
+            ),
+            (vec!["select-layout", "-t", "@2", "L2"], ""),
+            (vec!["select-pane", "-t", "%3"], ""),
+            (vec!["select-window", "-t", "@2"], ""),
+        ];
+        let sh = script
+            .iter()
+            .fold(ReplayShell::default(), |sh, (args, out)| {
+                sh.output("tmux", args, out)
+            });
+        restore_session(&sh, &snap, "/home/u")?;
+        let expected: Vec<Call> = script
+            .iter()
+            .map(|(args, _)| Call::Run {
+                program: "tmux".into(),
+                args: args.iter().map(|s| s.to_string()).collect(),
+            })
+            .collect();
+        assert_eq!(sh.calls(), expected);
+        Ok(())
+    }
+}
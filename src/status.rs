@@ -0,0 +1,382 @@
+use crate::config::{self, StatusConfig};
+use crate::connect::normalize_name;
+use crate::error;
+use crate::gc;
+use crate::git;
+use crate::pins;
+use crate::shell::Shell;
+use crate::tmux::{self, SessionInfo};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Seconds a segment is reused by default; tmux redraws `status-right`
+/// every `status-interval` (15s unless changed).
+const DEFAULT_CACHE: u64 = 5;
+
+/// Cache entries older than this are dropped when the file is rewritten.
+const CACHE_EXPIRY: u64 = 24 * 60 * 60;
+
+#[non_exhaustive]
+pub struct StatusOptions {
+    /// Session to describe; the active one when `None`
+    pub session: Option<String>,
+    /// Plain text instead of tmux `#[...]` styles
+    pub plain: bool,
+    /// Seconds a printed segment is reused; 0 turns the cache off
+    pub cache: u64,
+    /// Socket path of the tmux server, telling servers apart in the cache
+    pub server: String,
+    /// Unix time, for the cache
+    pub now: u64,
+}
+
+impl StatusOptions {
+    /// Config defaults overridden by command-line values.
+    pub fn new(
+        cfg: &StatusConfig,
+        session: Option<String>,
+        plain: bool,
+        cache: Option<&str>,
+    ) -> Result<StatusOptions> {
+        let cache = match cache.or(cfg.cache.as_deref()) {
+            Some(s) => gc::parse_duration(s)?,
+            None => DEFAULT_CACHE,
+        };
+        Ok(StatusOptions {
+            session,
+            plain: plain || cfg.plain,
+            cache,
+            server: String::new(),
+            now: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        })
+    }
+}
+
+/// What the segment shows about one session.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Status {
+    /// 1-based position of the session in [`session_order`]
+    pub position: usize,
+    pub total: usize,
+    /// Other sessions with a client attached
+    pub attached: usize,
+    /// Other sessions without one
+    pub detached: usize,
+    /// The session after this one, wrapping around
+    pub next: Option<String>,
+    /// Git branch of the session's start directory
+    pub branch: Option<String>,
+}
+
+/// Sessions in `nitro list` order, without moving the current one first:
+/// pinned sessions in pin order, then the rest by name.
+pub fn session_order(mut sessions: Vec<SessionInfo>, pins: &[pins::Pin]) -> Vec<SessionInfo> {
+    let rank = |s: &SessionInfo| {
+        let name = normalize_name(&s.name);
+        pins.iter()
+            .position(|p| p.session_name() == name)
+            .unwrap_or(pins.len())
+    };
+    sessions.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.name.cmp(&b.name)));
+    sessions
+}
+
+/// The status of `session`, from one `list-sessions` call (and one git call
+/// for the branch). `None` when the session is not running.
+pub fn status<S: Shell>(sh: &S, session: &str) -> Result<Option<Status>> {
+    // A broken pins file only changes the order
+    let pins = pins::load(sh).unwrap_or_default();
+    let sessions = session_order(tmux::list_session_info(sh)?, &pins);
+    let Some(i) = sessions.iter().position(|s| s.name == session) else {
+        return Ok(None);
+    };
+    let others = sessions.iter().filter(|s| s.name != session);
+    let attached = others.clone().filter(|s| s.attached > 0).count();
+    let next = &sessions[(i + 1) % sessions.len()];
+    let path = &sessions[i].path;
+    Ok(Some(Status {
+        position: i + 1,
+        total: sessions.len(),
+        attached,
+        detached: others.count() - attached,
+        next: Some(next.name.clone()).filter(|n| n != session),
+        branch: Some(path)
+            .filter(|p| !p.is_empty())
+            .and_then(|p| git::current_branch(sh, Path::new(p))),
+    }))
+}
+
+/// Escape `#` so tmux prints names literally instead of as formats.
+fn escape(s: &str) -> String {
+    s.replace('#', "##")
+}
+
+/// One line such as `2/5 +1 ~3 → web (main)`: position, other attached
+/// (`+`) and detached (`~`) sessions, the next session and the branch. Zero
+/// counts and missing parts are left out. Unless `plain`, the position is
+/// bold and the hint dim, as tmux styles.
+pub fn render(st: &Status, plain: bool) -> String {
+    let style = |on: &str, off: &str, s: String| {
+        if plain {
+            s
+        } else {
+            format!("#[{}]{}#[{}]", on, escape(&s), off)
+        }
+    };
+    let text = |s: &str| if plain { s.to_string() } else { escape(s) };
+    let mut parts = vec![style(
+        "bold",
+        "nobold",
+        format!("{}/{}", st.position, st.total),
+    )];
+    if st.attached > 0 {
+        parts.push(format!("+{}", st.attached));
+    }
+    if st.detached > 0 {
+        parts.push(format!("~{}", st.detached));
+    }
+    if let Some(next) = &st.next {
+        parts.push(style("dim", "nodim", format!("→ {}", next)));
+    }
+    if let Some(branch) = &st.branch {
+        parts.push(format!("({})", text(branch)));
+    }
+    parts.join(" ")
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cache {
+    entries: HashMap<String, CacheEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    time: u64,
+    line: String,
+}
+
+fn cache_path<S: Shell>(sh: &S) -> Option<PathBuf> {
+    config::cache_dir(sh).map(|d| d.join("status.json"))
+}
+
+/// A missing or unreadable cache is an empty one.
+fn load_cache(path: &Path) -> Cache {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Status lines drawn at the same time by several clients never read half
+/// a cache.
+fn save_cache(path: &Path, cache: &Cache) -> Result<()> {
+    config::write_cache(path, &serde_json::to_string(cache)?)
+}
+
+/// The segment for the session in `opts`, reused from the cache while it is
+/// younger than `opts.cache` seconds. Empty outside tmux.
+pub fn segment<S: Shell>(sh: &S, opts: &StatusOptions) -> Result<String> {
+    let session = match &opts.session {
+        Some(s) => Some(s.clone()),
+        None => tmux::active_session(sh)?,
+    };
+    let Some(session) = session else {
+        return Ok(String::new());
+    };
+    let key = format!("{}\t{}\t{}", opts.server, session, opts.plain);
+    let path = cache_path(sh).filter(|_| opts.cache > 0);
+    let mut cache = path.as_deref().map(load_cache).unwrap_or_default();
+    if let Some(hit) = cache.entries.get(&key)
+        && opts.now.saturating_sub(hit.time) < opts.cache
+    {
+        return Ok(hit.line.clone());
+    }
+    let line = match status(sh, &session)? {
+        Some(st) => render(&st, opts.plain),
+        None => String::new(),
+    };
+    if let Some(path) = path {
+        cache
+            .entries
+            .retain(|_, e| opts.now.saturating_sub(e.time) < CACHE_EXPIRY);
+        cache.entries.insert(
+            key,
+            CacheEntry {
+                time: opts.now,
+                line: line.clone(),
+            },
+        );
+        // A cache that cannot be written only costs the next redraw
+        if let Err(e) = save_cache(&path, &cache) {
+            error::warn(&e);
+        }
+    }
+    Ok(line)
+}
+
+pub fn run_status<S: Shell>(sh: &S, opts: &StatusOptions) -> Result<()> {
+    println!("{}", segment(sh, opts)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::ReplayShell;
+
+    const FMT: &str = "#{session_name}\t#{session_path}\t#{session_windows}\t#{session_attached}\t#{session_activity}\t#{session_last_attached}";
+
+    /// A server listing `sessions` for `calls` calls, and no pins whatever
+    /// the real data directory holds.
+    fn listing(sessions: &str, calls: usize) -> ReplayShell {
+        (0..calls).fold(
+            ReplayShell::default().env("NITRO_DATA_DIR", "/nitro/no-such-dir"),
+            |sh, _| sh.output("tmux", &["list-sessions", "-F", FMT], sessions),
+        )
+    }
+
+    fn info(name: &str, attached: u32) -> SessionInfo {
+        SessionInfo {
+            name: name.into(),
+            path: String::new(),
+            windows: 1,
+            attached,
+            activity: 0,
+            last_attached: 0,
+        }
+    }
+
+    fn scratch_dir(tag: &str) -> PathBuf {
+        let d = std::env::temp_dir().join(format!("nitro-status-{}-{}", tag, std::process::id()));
+        let _ = std::fs::remove_dir_all(&d);
+        d
+    }
+
+    #[test]
+    fn pinned_sessions_come_first() {
+        let pins = [pins::Pin::Session("web".into())];
+        let order = session_order(vec![info("b", 0), info("web", 0), info("a", 0)], &pins);
+        let names: Vec<&str> = order.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["web", "a", "b"]);
+    }
+
+    #[test]
+    fn counts_others_and_wraps_to_the_next_session() -> Result<()> {
+        let sh = listing(
+            "c\t/src/c\t1\t0\t0\t0\na\t/src/a\t2\t1\t0\t0\nb\t\t1\t1\t0\t0\n",
+            3,
+        )
+        .output(
+            "git",
+            &["-C", "/src/c", "rev-parse", "--abbrev-ref", "HEAD"],
+            "main\n",
+        );
+        let st = status(&sh, "c")?.unwrap();
+        assert_eq!(
+            st,
+            Status {
+                position: 3,
+                total: 3,
+                attached: 2,
+                detached: 0,
+                next: Some("a".into()),
+                branch: Some("main".into()),
+            }
+        );
+        assert_eq!(status(&sh, "b")?.unwrap().branch, None);
+        assert_eq!(status(&sh, "gone")?, None);
+        assert!(sh.unused().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn renders_tmux_styles_or_plain_text() {
+        let mut st = Status {
+            position: 2,
+            total: 5,
+            attached: 1,
+            detached: 3,
+            next: Some("web#1".into()),
+            branch: Some("main".into()),
+        };
+        assert_eq!(render(&st, true), "2/5 +1 ~3 → web#1 (main)");
+        assert_eq!(
+            render(&st, false),
+            "#[bold]2/5#[nobold] +1 ~3 #[dim]→ web##1#[nodim] (main)"
+        );
+        st.total = 1;
+        st.position = 1;
+        st.attached = 0;
+        st.detached = 0;
+        st.next = None;
+        st.branch = None;
+        assert_eq!(render(&st, true), "1/1");
+    }
+
+    #[test]
+    fn segment_is_cached_per_session() -> Result<()> {
+        let dir = scratch_dir("cache");
+        let sh = listing("a\t\t1\t1\t0\t0\nb\t\t1\t0\t0\t0\n", 4)
+            .env("NITRO_CACHE_DIR", &dir.display().to_string());
+        let mut opts = StatusOptions::new(&StatusConfig::default(), Some("a".into()), true, None)?;
+        opts.now = 100;
+        assert_eq!(segment(&sh, &opts)?, "1/2 ~1 → b");
+        opts.now = 104;
+        assert_eq!(segment(&sh, &opts)?, "1/2 ~1 → b");
+        assert_eq!(sh.unused().len(), 3);
+
+        opts.session = Some("b".into());
+        assert_eq!(segment(&sh, &opts)?, "2/2 +1 → a");
+        opts.session = Some("a".into());
+        opts.now = 105;
+        assert_eq!(segment(&sh, &opts)?, "1/2 ~1 → b");
+        assert_eq!(sh.unused().len(), 1);
+
+        opts.cache = 0;
+        segment(&sh, &opts)?;
+        assert!(sh.unused().is_empty());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn unwritable_cache_still_prints_the_segment() -> Result<()> {
+        let file = scratch_dir("unwritable");
+        std::fs::write(&file, "")?;
+        // The cache directory would have to live under a regular file
+        let sh = listing("a\t\t1\t1\t0\t0\n", 1)
+            .env("NITRO_CACHE_DIR", &file.join("cache").display().to_string());
+        let opts = StatusOptions::new(&StatusConfig::default(), Some("a".into()), true, None)?;
+        let line = segment(&sh, &opts);
+        std::fs::remove_file(&file)?;
+        assert_eq!(line?, "1/1");
+        Ok(())
+    }
+
+    #[test]
+    fn servers_are_cached_apart() -> Result<()> {
+        let dir = scratch_dir("servers");
+        let sh = listing("a\t\t1\t1\t0\t0\n", 1)
+            .output(
+                "tmux",
+                &["list-sessions", "-F", FMT],
+                "a\t\t1\t1\t0\t0\nb\t\t1\t0\t0\t0\n",
+            )
+            .env("NITRO_CACHE_DIR", &dir.display().to_string());
+        let mut opts = StatusOptions::new(&StatusConfig::default(), Some("a".into()), true, None)?;
+        opts.now = 100;
+        opts.server = "/tmp/tmux-1000/default".into();
+        assert_eq!(segment(&sh, &opts)?, "1/1");
+        opts.server = "/tmp/tmux-1000/work".into();
+        assert_eq!(segment(&sh, &opts)?, "1/2 ~1 → b");
+        assert!(sh.unused().is_empty());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+use crate::config::TemplateConfig;
+use crate::list::Source;
+use anyhow::{Context, Result, bail};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// A candidate field a template can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Field {
+    /// `pin`, `tmux` or `zoxide`
+    Source,
+    /// The source marker: `[t]`, or the glyph with `--icons`
+    Icon,
+    Name,
+    Path,
+    /// Git branch of the path
+    Branch,
+    Windows,
+    /// Number of attached clients
+    Attached,
+    /// zoxide score
+    Score,
+    /// Project type of the directory, e.g. `rust`
+    Project,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        Some(match name {
+            "source" => Field::Source,
+            "icon" => Field::Icon,
+            "name" => Field::Name,
+            "path" => Field::Path,
+            "branch" => Field::Branch,
+            "windows" => Field::Windows,
+            "attached" => Field::Attached,
+            "score" => Field::Score,
+            "type" => Field::Project,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field {
+        field: Field,
+        align: Align,
+        width: usize,
+        max: Option<usize>,
+    },
+}
+
+/// A parsed line template such as `{icon} {name:<20} {path:.40}`.
+///
+/// `{field}` is replaced by the field's value, empty when the candidate has
+/// none. After a `:` come an optional alignment (`<`, `>` or `^`), a minimum
+/// width, and `.N` to cut longer values to `N` columns (paths keep their
+/// end). Widths count display columns. `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Template> {
+        let mut parts = Vec::new();
+        let mut lit = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    lit.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    lit.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => bail!("invalid template {:?}: unclosed {{", text),
+                        }
+                    }
+                    let part = parse_field(&spec)
+                        .with_context(|| format!("invalid template {:?}", text))?;
+                    if !lit.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut lit)));
+                    }
+                    parts.push(part);
+                }
+                '}' => bail!("invalid template {:?}: unmatched }}", text),
+                c => lit.push(c),
+            }
+        }
+        if !lit.is_empty() {
+            parts.push(Part::Text(lit));
+        }
+        Ok(Template { parts })
+    }
+
+    /// Whether the template shows `field`.
+    pub fn uses(&self, field: Field) -> bool {
+        self.parts
+            .iter()
+            .any(|p| matches!(p, Part::Field { field: f, .. } if *f == field))
+    }
+
+    /// Fill in the fields with `value`, then let `paint` style each padded
+    /// value (e.g. color the icon). Trailing spaces are dropped.
+    pub fn render(
+        &self,
+        mut value: impl FnMut(Field) -> String,
+        paint: impl Fn(Field, String) -> String,
+    ) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(t) => out.push_str(t),
+                Part::Field {
+                    field,
+                    align,
+                    width,
+                    max,
+                } => {
+                    let mut v = value(*field);
+                    if let Some(max) = max {
+                        v = truncate(&v, *max, *field == Field::Path);
+                    }
+                    let v = match align {
+                        Align::Left => pad(&v, *width, false),
+                        Align::Right => pad(&v, *width, true),
+                        Align::Center => {
+                            let fill = width.saturating_sub(v.width());
+                            let left = " ".repeat(fill / 2);
+                            format!("{}{}{}", left, v, " ".repeat(fill - fill / 2))
+                        }
+                    };
+                    out.push_str(&paint(*field, v));
+                }
+            }
+        }
+        out.trim_end().to_string()
+    }
+}
+
+fn parse_field(spec: &str) -> Result<Part> {
+    let (name, format) = match spec.split_once(':') {
+        Some((n, f)) => (n.trim(), f),
+        None => (spec.trim(), ""),
+    };
+    let field = Field::parse(name).with_context(|| {
+        format!(
+            "unknown field {{{}}} (use source, icon, name, path, branch, windows, attached, score or type)",
+            name
+        )
+    })?;
+    let (align, rest) = match format.chars().next() {
+        Some('<') => (Align::Left, &format[1..]),
+        Some('>') => (Align::Right, &format[1..]),
+        Some('^') => (Align::Center, &format[1..]),
+        _ => (Align::Left, format),
+    };
+    let (width, max) = match rest.split_once('.') {
+        Some((w, m)) => (w, Some(m)),
+        None => (rest, None),
+    };
+    let number = |s: &str| {
+        s.parse::<usize>()
+            .with_context(|| format!("invalid width {:?} in {{{}}}", s, spec))
+    };
+    let width = if width.is_empty() { 0 } else { number(width)? };
+    let max = max.map(number).transpose()?;
+    Ok(Part::Field {
+        field,
+        align,
+        width,
+        max,
+    })
+}
+
+/// Cut `s` to `max` display columns, marking the cut with `…`. `keep_end`
+/// cuts the start instead, which suits paths.
+pub fn truncate(s: &str, max: usize, keep_end: bool) -> String {
+    if s.width() <= max {
+        return s.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let mut kept: Vec<char> = Vec::new();
+    let mut width = 1; // the ellipsis
+    let chars: Vec<char> = if keep_end {
+        s.chars().rev().collect()
+    } else {
+        s.chars().collect()
+    };
+    for c in chars {
+        let w = c.width().unwrap_or(0);
+        if width + w > max {
+            break;
+        }
+        width += w;
+        kept.push(c);
+    }
+    if keep_end {
+        std::iter::once('…').chain(kept.into_iter().rev()).collect()
+    } else {
+        kept.into_iter().chain(std::iter::once('…')).collect()
+    }
+}
+
+/// Pad `s` with spaces to `width` display columns, on the right or (with
+/// `right`) on the left.
+pub fn pad(s: &str, width: usize, right: bool) -> String {
+    let fill = " ".repeat(width.saturating_sub(s.width()));
+    if right {
+        fill + s
+    } else {
+        format!("{}{}", s, fill)
+    }
+}
+
+/// Line templates per list source; a source without one uses the built-in
+/// format.
+#[derive(Debug, Clone, Default)]
+pub struct Templates {
+    pub pin: Option<Template>,
+    pub tmux: Option<Template>,
+    pub zoxide: Option<Template>,
+}
+
+impl Templates {
+    /// Templates from the config, overridden by `--template` arguments. An
+    /// argument is `SOURCE=TEMPLATE` for one source or a bare template for
+    /// all of them. For each source the first of these is used: its
+    /// argument, the bare argument, its config entry, the config `default`.
+    pub fn new(cfg: &TemplateConfig, args: &[String]) -> Result<Templates> {
+        let mut default = None;
+        let mut by_source: [Option<&str>; 3] = [None, None, None];
+        for arg in args {
+            match arg
+                .split_once('=')
+                .and_then(|(src, t)| Some((source_index(src)?, t)))
+            {
+                Some((i, t)) => by_source[i] = Some(t),
+                None => default = Some(arg.as_str()),
+            }
+        }
+        let pick = |i: usize, cfg_one: &Option<String>| -> Result<Option<Template>> {
+            by_source[i]
+                .or(default)
+                .or(cfg_one.as_deref())
+                .or(cfg.default.as_deref())
+                .map(Template::parse)
+                .transpose()
+        };
+        Ok(Templates {
+            pin: pick(0, &cfg.pin)?,
+            tmux: pick(1, &cfg.tmux)?,
+            zoxide: pick(2, &cfg.zoxide)?,
+        })
+    }
+
+    pub fn get(&self, source: Source) -> Option<&Template> {
+        match source {
+            Source::Pin => self.pin.as_ref(),
+            Source::Tmux => self.tmux.as_ref(),
+            Source::Zoxide => self.zoxide.as_ref(),
+        }
+    }
+}
+
+fn source_index(name: &str) -> Option<usize> {
+    match name {
+        "pin" => Some(0),
+        "tmux" => Some(1),
+        "zoxide" => Some(2),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(t: &str, f: impl Fn(Field) -> &'static str) -> String {
+        Template::parse(t)
+            .unwrap()
+            .render(|field| f(field).to_string(), |_, v| v)
+    }
+
+    #[test]
+    fn fields_widths_and_escapes() {
+        let value = |f| match f {
+            Field::Name => "api",
+            Field::Path => "/src/api",
+            Field::Windows => "3",
+            _ => "",
+        };
+        assert_eq!(render("{name} {path}", value), "api /src/api");
+        assert_eq!(render("{name:<6}|{windows:>3}w", value), "api   |  3w");
+        assert_eq!(render("[{name:^7}]", value), "[  api  ]");
+        assert_eq!(render("{path:.5}", value), "…/api");
+        assert_eq!(render("{name:.2}", value), "a…");
+        assert_eq!(render("{{{name}}} {branch}", value), "{api}");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Template::parse("{nope}").is_err());
+        assert!(Template::parse("{name:x}").is_err());
+        assert!(Template::parse("{name").is_err());
+        assert!(Template::parse("name}").is_err());
+        assert!(Template::parse("{name:<4.2}").is_ok());
+    }
+
+    #[test]
+    fn uses_and_display_width() {
+        let t = Template::parse("{icon} {name:<4}|").unwrap();
+        assert!(t.uses(Field::Icon));
+        assert!(!t.uses(Field::Branch));
+        let s = t.render(|_| "日本".to_string(), |_, v| v);
+        assert_eq!(s, "日本 日本|");
+        assert_eq!(truncate("日本語", 4, false), "日…");
+    }
+
+    #[test]
+    fn arguments_override_config_per_source() -> Result<()> {
+        let cfg = TemplateConfig {
+            default: Some("{name}".into()),
+            tmux: Some("{name} {windows}".into()),
+            ..Default::default()
+        };
+        let t = Templates::new(&cfg, &[])?;
+        assert_eq!(t.pin, Some(Template::parse("{name}")?));
+        assert_eq!(t.tmux, Some(Template::parse("{name} {windows}")?));
+
+        let t = Templates::new(&cfg, &["zoxide={path}".into()])?;
+        assert_eq!(t.zoxide, Some(Template::parse("{path}")?));
+        assert_eq!(t.tmux, Some(Template::parse("{name} {windows}")?));
+        assert_eq!(t.pin, Some(Template::parse("{name}")?));
+        assert!(
+            Templates::new(&TemplateConfig::default(), &[])?
+                .tmux
+                .is_none()
+        );
+
+        let t = Templates::new(&cfg, &["{icon} {name}".into(), "tmux={name}".into()])?;
+        assert_eq!(t.pin, Some(Template::parse("{icon} {name}")?));
+        assert_eq!(t.tmux, Some(Template::parse("{name}")?));
+        assert!(Templates::new(&cfg, &["{bad}".into()]).is_err());
+        Ok(())
+    }
+}
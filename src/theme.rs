@@ -0,0 +1,429 @@
+use crate::config::ThemeConfig;
+use crate::gc;
+use crate::shell::Shell;
+use anyhow::{Context, Result, bail};
+use std::str::FromStr;
+
+/// `--color`: whether nitro colors its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Color terminals only, honoring `NO_COLOR` and `CLICOLOR_FORCE`
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ColorMode> {
+        match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => bail!("invalid color mode {:?} (use auto, always or never)", s),
+        }
+    }
+}
+
+/// Decide whether to color a stream. In `Auto` mode a non-empty `NO_COLOR`
+/// turns color off, a `CLICOLOR_FORCE` other than `0` turns it on, and
+/// otherwise the stream must be a terminal (and `CLICOLOR` not `0`).
+pub fn color_enabled<S: Shell>(sh: &S, mode: ColorMode, is_tty: bool) -> bool {
+    let set = |k: &str| sh.env_var(k).filter(|v| !v.is_empty());
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => {
+            if set("NO_COLOR").is_some() {
+                false
+            } else if set("CLICOLOR_FORCE").is_some_and(|v| v != "0") {
+                true
+            } else {
+                is_tty && set("CLICOLOR").is_none_or(|v| v != "0")
+            }
+        }
+    }
+}
+
+/// How many colors the terminal shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+/// Color depth from `COLORTERM` and `TERM`; 16 colors unless they say more.
+pub fn detect_depth<S: Shell>(sh: &S) -> ColorDepth {
+    let colorterm = sh.env_var("COLORTERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        return ColorDepth::TrueColor;
+    }
+    if sh.env_var("TERM").is_some_and(|t| t.contains("256color")) {
+        return ColorDepth::Ansi256;
+    }
+    ColorDepth::Ansi16
+}
+
+/// A color in one of the terminal palettes. Richer colors are approximated
+/// on terminals that cannot show them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// One of the 16 basic colors (8-15 are the bright ones)
+    Ansi(u8),
+    /// An index into the 256-color palette
+    Fixed(u8),
+    Rgb(u8, u8, u8),
+}
+
+const ANSI_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// xterm's default values of the 16 basic colors, for approximating.
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    /// `magenta`, `bright-blue`, a 256-color index such as `208`, or
+    /// `#rrggbb`.
+    fn from_str(s: &str) -> Result<Color> {
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix('#') {
+            let v = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)
+                .with_context(|| format!("invalid color {:?} (use #rrggbb)", s))?;
+            return Ok(Color::Rgb((v >> 16) as u8, (v >> 8) as u8, v as u8));
+        }
+        if let Ok(n) = s.parse::<u8>() {
+            return Ok(Color::Fixed(n));
+        }
+        let (bright, name) = match s.strip_prefix("bright-") {
+            Some(n) => (8, n),
+            None => (0, s),
+        };
+        match ANSI_NAMES.iter().position(|n| *n == name) {
+            Some(i) => Ok(Color::Ansi(i as u8 + bright)),
+            None => bail!(
+                "invalid color {:?} (use a name like magenta or bright-blue, 0-255, or #rrggbb)",
+                s
+            ),
+        }
+    }
+}
+
+impl Color {
+    fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Ansi(n) => ANSI_RGB[(n & 15) as usize],
+            Color::Fixed(n) if n < 16 => ANSI_RGB[n as usize],
+            Color::Fixed(n) if n >= 232 => {
+                let v = 8 + 10 * (n - 232);
+                (v, v, v)
+            }
+            Color::Fixed(n) => {
+                let level = |c: u8| if c == 0 { 0 } else { 55 + 40 * c };
+                let i = n - 16;
+                (level(i / 36), level((i / 6) % 6), level(i % 6))
+            }
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    /// This color as `depth` can show it.
+    fn downgrade(self, depth: ColorDepth) -> Color {
+        match (self, depth) {
+            (Color::Ansi(_), _) | (_, ColorDepth::TrueColor) => self,
+            (Color::Fixed(_), ColorDepth::Ansi256) => self,
+            (Color::Fixed(n), ColorDepth::Ansi16) if n < 16 => Color::Ansi(n),
+            (Color::Rgb(r, g, b), ColorDepth::Ansi256) => {
+                let step = |c: u8| ((c as u16 * 5 + 127) / 255) as u8;
+                Color::Fixed(16 + 36 * step(r) + 6 * step(g) + step(b))
+            }
+            (_, ColorDepth::Ansi16) => {
+                let (r, g, b) = self.rgb();
+                let dist = |&(cr, cg, cb): &(u8, u8, u8)| {
+                    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+                    d(r, cr) + d(g, cg) + d(b, cb)
+                };
+                let i = (0..16).min_by_key(|&i| dist(&ANSI_RGB[i])).unwrap_or(7);
+                Color::Ansi(i as u8)
+            }
+        }
+    }
+
+    fn code(self, depth: ColorDepth) -> String {
+        match self.downgrade(depth) {
+            Color::Ansi(n) if n < 8 => format!("{}", 30 + n),
+            Color::Ansi(n) => format!("{}", 90 + (n & 7)),
+            Color::Fixed(n) => format!("38;5;{}", n),
+            Color::Rgb(r, g, b) => format!("38;2;{};{};{}", r, g, b),
+        }
+    }
+}
+
+/// Foreground color and attributes for one kind of text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+}
+
+impl Style {
+    pub fn fg(color: Color) -> Style {
+        Style {
+            fg: Some(color),
+            ..Style::default()
+        }
+    }
+
+    fn codes(&self, depth: ColorDepth) -> Vec<String> {
+        let mut codes = Vec::new();
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if self.dim {
+            codes.push("2".to_string());
+        }
+        codes.extend(self.fg.map(|c| c.code(depth)));
+        codes
+    }
+}
+
+/// Styles for list output: one per source for the markers, plus the
+/// current session's name (bold) and sessions unused for `stale_after`
+/// seconds (dim).
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Theme {
+    pub pin: Style,
+    pub tmux: Style,
+    pub zoxide: Style,
+    pub active: Style,
+    pub stale: Style,
+    pub stale_after: u64,
+}
+
+/// Built-in palettes, by name.
+pub const THEMES: &[&str] = &["default", "nord", "gruvbox", "mono"];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            pin: Style::fg(Color::Ansi(3)),
+            tmux: Style::fg(Color::Ansi(5)),
+            zoxide: Style::fg(Color::Ansi(4)),
+            active: Style {
+                bold: true,
+                ..Style::default()
+            },
+            stale: Style {
+                dim: true,
+                ..Style::default()
+            },
+            stale_after: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl Theme {
+    /// A built-in palette (see [`THEMES`]).
+    pub fn named(name: &str) -> Option<Theme> {
+        let rgb = |r, g, b| Style::fg(Color::Rgb(r, g, b));
+        let base = Theme::default();
+        Some(match name {
+            "default" => base,
+            "nord" => Theme {
+                pin: rgb(0xeb, 0xcb, 0x8b),
+                tmux: rgb(0xb4, 0x8e, 0xad),
+                zoxide: rgb(0x81, 0xa1, 0xc1),
+                ..base
+            },
+            "gruvbox" => Theme {
+                pin: rgb(0xfa, 0xbd, 0x2f),
+                tmux: rgb(0xd3, 0x86, 0x9b),
+                zoxide: rgb(0x83, 0xa5, 0x98),
+                ..base
+            },
+            "mono" => Theme {
+                pin: Style::default(),
+                tmux: Style::default(),
+                zoxide: Style::default(),
+                ..base
+            },
+            _ => return None,
+        })
+    }
+
+    /// The `[theme]` palette with its per-source colors and stale age
+    /// applied.
+    pub fn from_config(cfg: &ThemeConfig) -> Result<Theme> {
+        let name = cfg.name.as_deref().unwrap_or("default");
+        let mut theme = Theme::named(name)
+            .with_context(|| format!("unknown theme {:?} (use {})", name, THEMES.join(", ")))?;
+        for (style, color) in [
+            (&mut theme.pin, &cfg.pin),
+            (&mut theme.tmux, &cfg.tmux),
+            (&mut theme.zoxide, &cfg.zoxide),
+        ] {
+            if let Some(c) = color {
+                style.fg = Some(c.parse()?);
+            }
+        }
+        if let Some(stale) = &cfg.stale {
+            theme.stale_after = gc::parse_duration(stale)?;
+        }
+        Ok(theme)
+    }
+}
+
+/// A theme and the depth to render it at; without a depth, text is left
+/// plain.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct Paint {
+    pub theme: Theme,
+    pub depth: Option<ColorDepth>,
+}
+
+impl Paint {
+    /// `theme` at the terminal's depth when coloring is enabled, else plain.
+    pub fn detect<S: Shell>(sh: &S, theme: Theme, mode: ColorMode, is_tty: bool) -> Paint {
+        let depth = color_enabled(sh, mode, is_tty).then(|| detect_depth(sh));
+        Paint { theme, depth }
+    }
+
+    /// Wrap `s` in the escapes for `style`.
+    pub fn style(&self, style: &Style, s: &str) -> String {
+        let Some(depth) = self.depth else {
+            return s.to_string();
+        };
+        let codes = style.codes(depth);
+        if codes.is_empty() || s.is_empty() {
+            return s.to_string();
+        }
+        format!("\u{001b}[{}m{}\u{001b}[0m", codes.join(";"), s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::ReplayShell;
+
+    #[test]
+    fn color_detection_order() {
+        let none = ReplayShell::default();
+        assert!(color_enabled(&none, ColorMode::Auto, true));
+        assert!(!color_enabled(&none, ColorMode::Auto, false));
+        assert!(color_enabled(&none, ColorMode::Always, false));
+        assert!(!color_enabled(&none, ColorMode::Never, true));
+
+        let no_color = ReplayShell::default()
+            .env("NO_COLOR", "1")
+            .env("CLICOLOR_FORCE", "1");
+        assert!(!color_enabled(&no_color, ColorMode::Auto, true));
+        assert!(color_enabled(&no_color, ColorMode::Always, true));
+        let empty = ReplayShell::default().env("NO_COLOR", "");
+        assert!(color_enabled(&empty, ColorMode::Auto, true));
+        let force = ReplayShell::default().env("CLICOLOR_FORCE", "1");
+        assert!(color_enabled(&force, ColorMode::Auto, false));
+        let off = ReplayShell::default().env("CLICOLOR", "0");
+        assert!(!color_enabled(&off, ColorMode::Auto, true));
+    }
+
+    #[test]
+    fn depth_from_env() {
+        assert_eq!(detect_depth(&ReplayShell::default()), ColorDepth::Ansi16);
+        let t = ReplayShell::default().env("TERM", "xterm-256color");
+        assert_eq!(detect_depth(&t), ColorDepth::Ansi256);
+        let t = ReplayShell::default()
+            .env("COLORTERM", "truecolor")
+            .env("TERM", "xterm");
+        assert_eq!(detect_depth(&t), ColorDepth::TrueColor);
+    }
+
+    #[test]
+    fn parse_colors() -> Result<()> {
+        assert_eq!("magenta".parse::<Color>()?, Color::Ansi(5));
+        assert_eq!("bright-blue".parse::<Color>()?, Color::Ansi(12));
+        assert_eq!("208".parse::<Color>()?, Color::Fixed(208));
+        assert_eq!("#b48ead".parse::<Color>()?, Color::Rgb(0xb4, 0x8e, 0xad));
+        assert!("#b48".parse::<Color>().is_err());
+        assert!("purple".parse::<Color>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn colors_degrade_to_terminal_depth() {
+        let paint = |depth| Paint {
+            theme: Theme::default(),
+            depth,
+        };
+        let s = Style::fg(Color::Rgb(255, 0, 0));
+        assert_eq!(
+            paint(Some(ColorDepth::TrueColor)).style(&s, "x"),
+            "\u{1b}[38;2;255;0;0mx\u{1b}[0m"
+        );
+        assert_eq!(
+            paint(Some(ColorDepth::Ansi256)).style(&s, "x"),
+            "\u{1b}[38;5;196mx\u{1b}[0m"
+        );
+        assert_eq!(
+            paint(Some(ColorDepth::Ansi16)).style(&s, "x"),
+            "\u{1b}[91mx\u{1b}[0m"
+        );
+        let bold = Style {
+            bold: true,
+            ..Style::fg(Color::Ansi(5))
+        };
+        assert_eq!(
+            paint(Some(ColorDepth::Ansi16)).style(&bold, "x"),
+            "\u{1b}[1;35mx\u{1b}[0m"
+        );
+        assert_eq!(paint(None).style(&bold, "x"), "x");
+    }
+
+    #[test]
+    fn themes_from_config() -> Result<()> {
+        let cfg = ThemeConfig {
+            name: Some("nord".into()),
+            tmux: Some("bright-magenta".into()),
+            stale: Some("2d".into()),
+            ..Default::default()
+        };
+        let t = Theme::from_config(&cfg)?;
+        assert_eq!(t.tmux.fg, Some(Color::Ansi(13)));
+        assert_eq!(t.pin.fg, Some(Color::Rgb(0xeb, 0xcb, 0x8b)));
+        assert_eq!(t.stale_after, 2 * 24 * 60 * 60);
+        let cfg = ThemeConfig {
+            name: Some("neon".into()),
+            ..Default::default()
+        };
+        assert!(Theme::from_config(&cfg).is_err());
+        assert!(THEMES.iter().all(|n| Theme::named(n).is_some()));
+        Ok(())
+    }
+}
@@ -1,10 +1,181 @@
+use crate::error::NitroError;
 use crate::shell::Shell;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use std::path::PathBuf;
 
+/// Which tmux server to talk to.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Socket {
+    #[default]
+    Default,
+    /// Socket name under the per-user tmux directory (`tmux -L`)
+    Name(String),
+    /// Explicit socket path (`tmux -S`)
+    Path(PathBuf),
+}
+
+impl Socket {
+    /// Interpret a `NITRO_TMUX_SOCKET` style value: a path if it contains a
+    /// `/`, otherwise a socket name.
+    pub fn parse(value: &str) -> Socket {
+        if value.contains('/') {
+            Socket::Path(PathBuf::from(value))
+        } else {
+            Socket::Name(value.to_string())
+        }
+    }
+
+    /// The socket file tmux opens for this server: a `-S` path as given,
+    /// the server of `$TMUX` when no socket was chosen, otherwise the name
+    /// in [`socket_dir`].
+    pub fn path<S: Shell>(&self, sh: &S) -> Result<PathBuf> {
+        let name = match self {
+            Socket::Path(p) => return Ok(p.clone()),
+            Socket::Name(n) => n.as_str(),
+            Socket::Default => {
+                let current = sh.env_var("TMUX");
+                match current.as_deref().and_then(|v| v.split(',').next()) {
+                    Some(p) if !p.is_empty() => return Ok(PathBuf::from(p)),
+                    _ => "default",
+                }
+            }
+        };
+        Ok(socket_dir(sh)?.join(name))
+    }
+
+    /// The `-L`/`-S` arguments selecting this server; nitro takes the same.
+    pub fn args(&self) -> Vec<String> {
+        match self {
+            Socket::Default => Vec::new(),
+            Socket::Name(n) => vec!["-L".into(), n.clone()],
+            Socket::Path(p) => vec!["-S".into(), p.to_string_lossy().to_string()],
+        }
+    }
+}
+
+/// Shell adapter that points every tmux invocation at one server by
+/// prefixing `-L`/`-S`. Other programs pass through untouched, and so do
+/// tmux calls that already name a server: when adapters nest (a `server:`
+/// line under a global `-S`), the innermost socket wins.
+pub struct WithSocket<'a, S: Shell> {
+    inner: &'a S,
+    prefix: Vec<String>,
+}
+
+impl<'a, S: Shell> WithSocket<'a, S> {
+    pub fn new(inner: &'a S, socket: &Socket) -> Self {
+        WithSocket {
+            inner,
+            prefix: socket.args(),
+        }
+    }
+
+    fn full_args<'b>(&'b self, program: &str, args: &[&'b str]) -> Vec<&'b str> {
+        if program != "tmux" || matches!(args.first(), Some(&("-L" | "-S"))) {
+            return args.to_vec();
+        }
+        self.prefix
+            .iter()
+            .map(String::as_str)
+            .chain(args.iter().copied())
+            .collect()
+    }
+}
+
+impl<S: Shell> Shell for WithSocket<'_, S> {
+    fn run(&self, program: &str, args: &[&str]) -> Result<String> {
+        self.inner.run(program, &self.full_args(program, args))
+    }
+    fn run_status(&self, program: &str, args: &[&str]) -> Result<bool> {
+        self.inner
+            .run_status(program, &self.full_args(program, args))
+    }
+    fn run_tty(&self, program: &str, args: &[&str]) -> Result<()> {
+        self.inner.run_tty(program, &self.full_args(program, args))
+    }
+    fn run_picker(&self, program: &str, args: &[&str], input: &str) -> Result<String> {
+        self.inner
+            .run_picker(program, &self.full_args(program, args), input)
+    }
+    fn run_interactive(&self, program: &str, args: &[&str]) -> Result<()> {
+        self.inner
+            .run_interactive(program, &self.full_args(program, args))
+    }
+    fn env_var(&self, key: &str) -> Option<String> {
+        self.inner.env_var(key)
+    }
+    fn confirm(&self, prompt: &str) -> bool {
+        self.inner.confirm(prompt)
+    }
+    fn has_terminal(&self) -> bool {
+        self.inner.has_terminal()
+    }
+}
+
+/// Per-user socket directory: `$TMUX_TMPDIR/tmux-$UID` (default `/tmp`).
+pub fn socket_dir<S: Shell>(sh: &S) -> Result<PathBuf> {
+    let base = sh
+        .env_var("TMUX_TMPDIR")
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "/tmp".into());
+    let uid = sh.run("id", &["-u"]).context("failed to determine uid")?;
+    Ok(PathBuf::from(base).join(format!("tmux-{}", uid.trim())))
+}
+
+/// Names of the server sockets in `socket_dir`, `default` first then sorted.
+/// Usable with `Socket::Name`. A missing directory means no servers.
+pub fn server_sockets<S: Shell>(sh: &S) -> Result<Vec<String>> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let dir = socket_dir(sh)?;
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", dir.display())),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_socket() {
+            names.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    names.sort_by_key(|n| (n != "default", n.clone()));
+    Ok(names)
+}
+
+/// Whether tmux stderr means "no server is listening" rather than a real
+/// failure. A leftover socket whose server died refuses connections.
+fn is_no_server(detail: &str) -> bool {
+    detail.contains("no server running")
+        || (detail.contains("error connecting to")
+            && (detail.contains("No such file") || detail.contains("Connection refused")))
+}
+
+/// Run a tmux command, mapping a missing server to `NitroError::NoServer`.
+fn run_tmux<S: Shell>(sh: &S, args: &[&str]) -> Result<String> {
+    sh.run("tmux", args)
+        .map_err(|e| match crate::error::find(&e) {
+            Some(NitroError::CommandFailed { detail, .. }) if is_no_server(detail) => {
+                NitroError::NoServer.into()
+            }
+            _ => e,
+        })
+}
+
+/// Like `run_tmux`, but a missing server yields empty output: no server
+/// simply means there are no sessions yet.
+fn run_tmux_query<S: Shell>(sh: &S, args: &[&str]) -> Result<String> {
+    match run_tmux(sh, args) {
+        Err(e) if crate::error::find(&e) == Some(&NitroError::NoServer) => Ok(String::new()),
+        res => res,
+    }
+}
+
+/// Session names, sorted. Empty when no server is running; other failures
+/// (tmux missing, socket permissions, ...) are returned as errors.
 pub fn list_sessions<S: Shell>(sh: &S) -> Result<Vec<String>> {
-    let out = sh
-        .run("tmux", &["list-sessions", "-F", "#S"])
-        .unwrap_or_default();
+    let out = run_tmux_query(sh, &["list-sessions", "-F", "#S"])?;
     let mut items: Vec<String> = out
         .lines()
         .map(|s| s.trim().to_string())
@@ -14,25 +185,167 @@ pub fn list_sessions<S: Shell>(sh: &S) -> Result<Vec<String>> {
     Ok(items)
 }
 
+/// Run a tmux listing whose `-F` format separates fields with tabs and
+/// split each line. Empty when no server is running.
+fn query_rows<S: Shell>(sh: &S, args: &[&str]) -> Result<Vec<Vec<String>>> {
+    let out = run_tmux_query(sh, args)?;
+    Ok(out
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.split('\t').map(str::to_string).collect())
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct WindowInfo {
+    pub session: String,
+    pub index: u32,
+    pub name: String,
+    pub layout: String,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct PaneInfo {
+    pub session: String,
+    pub window_index: u32,
+    pub index: u32,
+    pub path: String,
+    pub command: String,
+    pub active: bool,
+    /// Process id of the pane's shell
+    pub pid: u32,
+}
+
+/// Per-session details used by `nitro gc` and the long listing.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct SessionInfo {
+    pub name: String,
+    pub path: String,
+    pub windows: u32,
+    /// Number of attached clients
+    pub attached: u32,
+    /// Unix time of the last activity in the session
+    pub activity: u64,
+    /// Unix time a client last attached, 0 if never
+    pub last_attached: u64,
+}
+
+impl SessionInfo {
+    /// Unix time the session was last used, by activity or attach.
+    pub fn last_used(&self) -> u64 {
+        self.activity.max(self.last_attached)
+    }
+}
+
+/// Details of every session, in tmux's order.
+pub fn list_session_info<S: Shell>(sh: &S) -> Result<Vec<SessionInfo>> {
+    let fmt = "#{session_name}\t#{session_path}\t#{session_windows}\t#{session_attached}\t#{session_activity}\t#{session_last_attached}";
+    let rows = query_rows(sh, &["list-sessions", "-F", fmt])?;
+    Ok(rows
+        .into_iter()
+        .filter(|r| r.len() >= 6)
+        .map(|r| SessionInfo {
+            name: r[0].clone(),
+            path: r[1].clone(),
+            windows: r[2].parse().unwrap_or(0),
+            attached: r[3].parse().unwrap_or(0),
+            activity: r[4].parse().unwrap_or(0),
+            last_attached: r[5].parse().unwrap_or(0),
+        })
+        .collect())
+}
+
+/// A target matching exactly the session `name`; a bare name also matches
+/// other sessions by prefix or pattern when no session has that name.
+fn exact(name: &str) -> String {
+    format!("={}", name)
+}
+
+pub fn kill_session<S: Shell>(sh: &S, name: &str) -> Result<()> {
+    let _ = run_tmux(sh, &["kill-session", "-t", &exact(name)])?;
+    Ok(())
+}
+
+pub fn rename_session<S: Shell>(sh: &S, name: &str, new_name: &str) -> Result<()> {
+    let _ = run_tmux(sh, &["rename-session", "-t", &exact(name), new_name])?;
+    Ok(())
+}
+
+/// What the active pane of a session shows, with its colors.
+pub fn capture_pane<S: Shell>(sh: &S, session: &str) -> Result<String> {
+    let target = format!("={}:", session);
+    run_tmux(sh, &["capture-pane", "-e", "-p", "-t", &target])
+}
+
+/// (name, start directory) of every session.
+pub fn list_session_paths<S: Shell>(sh: &S) -> Result<Vec<(String, String)>> {
+    let rows = query_rows(
+        sh,
+        &["list-sessions", "-F", "#{session_name}\t#{session_path}"],
+    )?;
+    Ok(rows
+        .into_iter()
+        .filter(|r| r.len() >= 2)
+        .map(|r| (r[0].clone(), r[1].clone()))
+        .collect())
+}
+
+pub fn list_all_windows<S: Shell>(sh: &S) -> Result<Vec<WindowInfo>> {
+    let fmt =
+        "#{session_name}\t#{window_index}\t#{window_name}\t#{window_layout}\t#{window_active}";
+    let rows = query_rows(sh, &["list-windows", "-a", "-F", fmt])?;
+    Ok(rows
+        .into_iter()
+        .filter(|r| r.len() >= 5)
+        .map(|r| WindowInfo {
+            session: r[0].clone(),
+            index: r[1].parse().unwrap_or(0),
+            name: r[2].clone(),
+            layout: r[3].clone(),
+            active: r[4] == "1",
+        })
+        .collect())
+}
+
+pub fn list_all_panes<S: Shell>(sh: &S) -> Result<Vec<PaneInfo>> {
+    let fmt = "#{session_name}\t#{window_index}\t#{pane_index}\t#{pane_current_path}\t#{pane_current_command}\t#{pane_active}\t#{pane_pid}";
+    let rows = query_rows(sh, &["list-panes", "-a", "-F", fmt])?;
+    Ok(rows
+        .into_iter()
+        .filter(|r| r.len() >= 7)
+        .map(|r| PaneInfo {
+            session: r[0].clone(),
+            window_index: r[1].parse().unwrap_or(0),
+            index: r[2].parse().unwrap_or(0),
+            path: r[3].clone(),
+            command: r[4].clone(),
+            active: r[5] == "1",
+            pid: r[6].parse().unwrap_or(0),
+        })
+        .collect())
+}
+
 /// Best-effort detection of the currently active/attached session name.
-/// Returns None if not determinable.
-pub fn active_session<S: Shell>(sh: &S) -> Option<String> {
-    // If inside tmux, display-message yields the current session.
-    let disp = sh
-        .run("tmux", &["display-message", "-p", "-F", "#S"])
-        .unwrap_or_default();
+/// Returns Ok(None) if not determinable or no server is running.
+pub fn active_session<S: Shell>(sh: &S) -> Result<Option<String>> {
+    // If inside tmux, display-message yields the current session. Outside a
+    // client it may legitimately fail, so only a missing server stops here.
+    let disp = match run_tmux(sh, &["display-message", "-p", "-F", "#S"]) {
+        Ok(out) => out,
+        Err(e) if crate::error::find(&e) == Some(&NitroError::NoServer) => return Ok(None),
+        Err(_) => String::new(),
+    };
     let name = disp.lines().next().map(|s| s.trim()).unwrap_or("");
     if !name.is_empty() {
-        return Some(name.to_string());
+        return Ok(Some(name.to_string()));
     }
 
     // Fallback: scan sessions for an attached one.
-    let out = sh
-        .run(
-            "tmux",
-            &["list-sessions", "-F", "#{?session_attached,1,0}\t#S"],
-        )
-        .unwrap_or_default();
+    let out = run_tmux_query(sh, &["list-sessions", "-F", "#{?session_attached,1,0}\t#S"])?;
     let mut attached: Vec<String> = out
         .lines()
         .filter_map(|line| {
@@ -51,26 +364,211 @@ pub fn active_session<S: Shell>(sh: &S) -> Option<String> {
         })
         .collect();
     attached.sort();
-    attached.into_iter().next()
+    Ok(attached.into_iter().next())
 }
 
 pub fn has_session<S: Shell>(sh: &S, name: &str) -> Result<bool> {
     // tmux has-session returns non-zero if not exists
-    let ok = sh.run_status("tmux", &["has-session", "-t", name])?;
+    let ok = sh.run_status("tmux", &["has-session", "-t", &exact(name)])?;
     Ok(ok)
 }
 
-pub fn create_session<S: Shell>(sh: &S, name: &str, dir: &str) -> Result<()> {
-    let _ = sh.run("tmux", &["new-session", "-ds", name, "-c", dir])?;
+/// Start directory of an existing session, if tmux reports one.
+pub fn session_path<S: Shell>(sh: &S, name: &str) -> Result<Option<String>> {
+    let out = run_tmux(
+        sh,
+        &[
+            "display-message",
+            "-p",
+            "-t",
+            &exact(name),
+            "#{session_path}",
+        ],
+    )?;
+    let path = out.lines().next().map(str::trim).unwrap_or("");
+    Ok((!path.is_empty()).then(|| path.to_string()))
+}
+
+/// Create a detached session; `env` is set in the session environment so
+/// every pane starts with it.
+pub fn create_session<S: Shell>(
+    sh: &S,
+    name: &str,
+    dir: &str,
+    env: &[(String, String)],
+) -> Result<()> {
+    let vars: Vec<String> = env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    let mut args = vec!["new-session", "-ds", name, "-c", dir];
+    for v in &vars {
+        args.extend(["-e", v.as_str()]);
+    }
+    let _ = run_tmux(sh, &args)?;
+    Ok(())
+}
+
+/// Ids of the first window and pane of a session (or of a window target).
+pub fn first_window_and_pane<S: Shell>(sh: &S, session: &str) -> Result<(String, String)> {
+    let out = run_tmux(
+        sh,
+        &[
+            "list-panes",
+            "-t",
+            session,
+            "-F",
+            "#{window_id}\t#{pane_id}",
+        ],
+    )?;
+    out.lines()
+        .next()
+        .and_then(|l| l.split_once('\t'))
+        .map(|(w, p)| (w.trim().to_string(), p.trim().to_string()))
+        .with_context(|| format!("no panes in session {}", session))
+}
+
+/// Append a window to a session; returns the new window id.
+pub fn new_window<S: Shell>(sh: &S, session: &str, name: &str, dir: &str) -> Result<String> {
+    let target = format!("{}:", session);
+    let out = run_tmux(
+        sh,
+        &[
+            "new-window",
+            "-d",
+            "-P",
+            "-F",
+            "#{window_id}",
+            "-t",
+            &target,
+            "-n",
+            name,
+            "-c",
+            dir,
+        ],
+    )?;
+    Ok(out.trim().to_string())
+}
+
+/// Split a window; returns the new pane id.
+pub fn split_window<S: Shell>(sh: &S, window: &str, dir: &str) -> Result<String> {
+    let out = run_tmux(
+        sh,
+        &[
+            "split-window",
+            "-d",
+            "-P",
+            "-F",
+            "#{pane_id}",
+            "-t",
+            window,
+            "-c",
+            dir,
+        ],
+    )?;
+    Ok(out.trim().to_string())
+}
+
+pub fn rename_window<S: Shell>(sh: &S, window: &str, name: &str) -> Result<()> {
+    let _ = run_tmux(sh, &["rename-window", "-t", window, name])?;
+    Ok(())
+}
+
+pub fn select_layout<S: Shell>(sh: &S, window: &str, layout: &str) -> Result<()> {
+    let _ = run_tmux(sh, &["select-layout", "-t", window, layout])?;
+    Ok(())
+}
+
+pub fn select_window<S: Shell>(sh: &S, window: &str) -> Result<()> {
+    let _ = run_tmux(sh, &["select-window", "-t", window])?;
+    Ok(())
+}
+
+pub fn select_pane<S: Shell>(sh: &S, pane: &str) -> Result<()> {
+    let _ = run_tmux(sh, &["select-pane", "-t", pane])?;
+    Ok(())
+}
+
+/// Type a command line into a pane and press Enter.
+pub fn send_line<S: Shell>(sh: &S, pane: &str, line: &str) -> Result<()> {
+    let _ = run_tmux(sh, &["send-keys", "-t", pane, line, "Enter"])?;
+    Ok(())
+}
+
+/// Type text into a pane literally, without pressing Enter.
+pub fn type_text<S: Shell>(sh: &S, pane: &str, text: &str) -> Result<()> {
+    let _ = run_tmux(sh, &["send-keys", "-t", pane, "-l", text])?;
+    Ok(())
+}
+
+/// Copy `vars` from nitro's environment into a session's environment so
+/// new panes see current values; unset ones are marked for removal, as
+/// tmux's own `update-environment` does.
+pub fn refresh_environment<S: Shell>(sh: &S, name: &str, vars: &[String]) -> Result<()> {
+    for var in vars {
+        match sh.env_var(var) {
+            Some(value) => run_tmux(sh, &["set-environment", "-t", name, var, &value])?,
+            None => run_tmux(sh, &["set-environment", "-t", name, "-r", var])?,
+        };
+    }
+    Ok(())
+}
+
+const SHELLS: &[&str] = &["bash", "zsh", "sh", "dash", "ksh", "fish"];
+
+/// Whether a `pane_current_command` is an interactive shell (login shells
+/// show up as `-bash`).
+pub fn is_shell_command(cmd: &str) -> bool {
+    SHELLS.contains(&cmd.trim().trim_start_matches('-'))
+}
+
+pub(crate) fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Type an export of `vars` into every pane of the session that is sitting
+/// at a shell prompt, so already running shells pick up new values too.
+/// The leading space keeps the line out of shell history.
+pub fn broadcast_environment<S: Shell>(sh: &S, name: &str, vars: &[String]) -> Result<()> {
+    let out = run_tmux(
+        sh,
+        &[
+            "list-panes",
+            "-s",
+            "-t",
+            name,
+            "-F",
+            "#{pane_id}\t#{pane_current_command}",
+        ],
+    )?;
+    for line in out.lines() {
+        let Some((pane, cmd)) = line.split_once('\t') else {
+            continue;
+        };
+        if !is_shell_command(cmd) {
+            continue;
+        }
+        let fish = cmd.trim().trim_start_matches('-') == "fish";
+        let parts: Vec<String> = vars
+            .iter()
+            .map(|var| match (sh.env_var(var), fish) {
+                (Some(v), false) => format!("export {}={}", var, shell_quote(&v)),
+                (Some(v), true) => format!("set -gx {} {}", var, shell_quote(&v)),
+                (None, false) => format!("unset {}", var),
+                (None, true) => format!("set -e {}", var),
+            })
+            .collect();
+        if parts.is_empty() {
+            continue;
+        }
+        send_line(sh, pane, &format!(" {}", parts.join("; ")))?;
+    }
     Ok(())
 }
 
 pub fn attach_or_switch<S: Shell>(sh: &S, name: &str) -> Result<()> {
     if sh.env_var("TMUX").is_some() {
-        let _ = sh.run("tmux", &["switch-client", "-t", name])?;
+        let _ = run_tmux(sh, &["switch-client", "-t", &exact(name)])?;
     } else {
         // Attach must run with a TTY; inherit stdio so tmux sees a terminal
-        sh.run_tty("tmux", &["attach", "-t", name])?;
+        sh.run_tty("tmux", &["attach", "-t", &exact(name)])?;
     }
     Ok(())
 }
@@ -78,84 +576,240 @@ pub fn attach_or_switch<S: Shell>(sh: &S, name: &str) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transcript::{Call, Outcome, ReplayShell};
     use anyhow::Result;
-    use std::collections::HashMap;
 
-    #[derive(Default)]
-    struct MockShell {
-        status: HashMap<(String, Vec<String>), bool>,
-        env: HashMap<String, String>,
+    const DISPLAY: [&str; 4] = ["display-message", "-p", "-F", "#S"];
+    const SESSIONS: [&str; 3] = ["list-sessions", "-F", "#S"];
+
+    /// A shell on which every tmux call in `calls` fails with `detail`.
+    fn failing(detail: &str, calls: &[&[&str]]) -> ReplayShell {
+        calls.iter().fold(ReplayShell::default(), |sh, args| {
+            sh.fail(
+                "tmux",
+                args,
+                NitroError::command_failed("tmux", args, detail),
+            )
+        })
     }
 
-    impl MockShell {
-        fn status(mut self, program: &str, args: &[&str], ok: bool) -> Self {
-            self.status.insert(
-                (program.into(), args.iter().map(|s| s.to_string()).collect()),
-                ok,
+    #[test]
+    fn list_sessions_sorts() -> Result<()> {
+        let sh = ReplayShell::default().output("tmux", &SESSIONS, "b\na\n");
+        let v = list_sessions(&sh)?;
+        assert_eq!(v, vec!["a", "b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn list_sessions_empty_without_server_error_otherwise() {
+        let no_server = failing(
+            "no server running on /tmp/tmux-1000/default",
+            &[&SESSIONS, &DISPLAY],
+        );
+        assert!(list_sessions(&no_server).unwrap().is_empty());
+        assert_eq!(active_session(&no_server).unwrap(), None);
+
+        let denied = failing(
+            "error connecting to /tmp/tmux-1000/default (Permission denied)",
+            &[
+                &SESSIONS,
+                &DISPLAY,
+                &["list-sessions", "-F", "#{?session_attached,1,0}\t#S"],
+            ],
+        );
+        assert!(list_sessions(&denied).is_err());
+        assert!(active_session(&denied).is_err());
+        assert!(denied.unused().is_empty());
+    }
+
+    #[test]
+    fn has_session_true_false() -> Result<()> {
+        let sh = ReplayShell::default()
+            .status("tmux", &["has-session", "-t", "=x"], true)
+            .status("tmux", &["has-session", "-t", "=x"], false);
+        assert!(has_session(&sh, "x")?);
+        assert!(!has_session(&sh, "x")?);
+        Ok(())
+    }
+
+    #[test]
+    fn run_tmux_maps_no_server() {
+        let sh = failing(
+            "no server running on /tmp/tmux-1000/default",
+            &[&["switch-client", "-t", "=x"]],
+        )
+        .env("TMUX", "1");
+        let err = attach_or_switch(&sh, "x").unwrap_err();
+        assert_eq!(crate::error::find(&err), Some(&NitroError::NoServer));
+    }
+
+    #[test]
+    fn with_socket_prefixes_tmux_calls_only() -> Result<()> {
+        let rec = ReplayShell::default()
+            .status("tmux", &["-L", "work", "has-session", "-t", "=x"], true)
+            .tty("tmux", &["-L", "work", "attach", "-t", "=x"])
+            .output("zoxide", &["query", "-l"], "")
+            .output(
+                "tmux",
+                &["-S", "/s/sock", "new-session", "-ds", "x", "-c", "/d"],
+                "",
+            )
+            .output("tmux", &SESSIONS, "");
+        let sh = WithSocket::new(&rec, &Socket::Name("work".into()));
+        has_session(&sh, "x")?;
+        attach_or_switch(&sh, "x")?;
+        sh.run("zoxide", &["query", "-l"])?;
+        let sh = WithSocket::new(&rec, &Socket::Path("/s/sock".into()));
+        create_session(&sh, "x", "/d", &[])?;
+        let sh = WithSocket::new(&rec, &Socket::Default);
+        list_sessions(&sh)?;
+        assert!(rec.unused().is_empty());
+
+        // A per-line server inside a global -S targets that server only
+        let rec =
+            ReplayShell::default().status("tmux", &["-L", "work", "has-session", "-t", "=x"], true);
+        let outer = WithSocket::new(&rec, &Socket::Path("/s/sock".into()));
+        let inner = WithSocket::new(&outer, &Socket::Name("work".into()));
+        assert!(has_session(&inner, "x")?);
+        assert!(rec.unused().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn create_session_passes_env() -> Result<()> {
+        let sh = ReplayShell::default().output(
+            "tmux",
+            &[
+                "new-session",
+                "-ds",
+                "x",
+                "-c",
+                "/d",
+                "-e",
+                "A=1",
+                "-e",
+                "B=x y",
+            ],
+            "",
+        );
+        let env = vec![
+            ("A".to_string(), "1".to_string()),
+            ("B".to_string(), "x y".to_string()),
+        ];
+        create_session(&sh, "x", "/d", &env)?;
+        assert!(sh.unused().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn refresh_environment_sets_and_removes() -> Result<()> {
+        let sh = ReplayShell::default()
+            .env("DISPLAY", ":1")
+            .output("tmux", &["set-environment", "-t", "s", "DISPLAY", ":1"], "")
+            .output(
+                "tmux",
+                &["set-environment", "-t", "s", "-r", "SSH_AUTH_SOCK"],
+                "",
             );
-            self
-        }
-        fn with_env(mut self, k: &str, v: &str) -> Self {
-            self.env.insert(k.into(), v.into());
-            self
-        }
+        refresh_environment(&sh, "s", &["DISPLAY".into(), "SSH_AUTH_SOCK".into()])?;
+        assert!(sh.unused().is_empty());
+        Ok(())
     }
 
-    impl crate::shell::Shell for MockShell {
-        fn run(&self, _program: &str, _args: &[&str]) -> Result<String> {
-            Ok(String::new())
-        }
-        fn run_status(&self, program: &str, args: &[&str]) -> Result<bool> {
-            Ok(*self
-                .status
-                .get(&(program.into(), args.iter().map(|s| s.to_string()).collect()))
-                .unwrap_or(&true))
-        }
-        fn run_tty(&self, _program: &str, _args: &[&str]) -> Result<()> {
-            Ok(())
-        }
-        fn env_var(&self, key: &str) -> Option<String> {
-            self.env.get(key).cloned()
-        }
+    #[test]
+    fn broadcast_types_exports_into_shell_panes() -> Result<()> {
+        let panes = [
+            "list-panes",
+            "-s",
+            "-t",
+            "s",
+            "-F",
+            "#{pane_id}\t#{pane_current_command}",
+        ];
+        let keys = |pane: &str, line: &str| Call::Run {
+            program: "tmux".into(),
+            args: ["send-keys", "-t", pane, line, "Enter"]
+                .map(String::from)
+                .to_vec(),
+        };
+        let posix = " export SSH_AUTH_SOCK='/tmp/it'\\''s'; unset DISPLAY";
+        let fish = " set -gx SSH_AUTH_SOCK '/tmp/it'\\''s'; set -e DISPLAY";
+        let sh = ReplayShell::default()
+            .env("SSH_AUTH_SOCK", "/tmp/it's")
+            .output("tmux", &panes, "%1\tzsh\n%2\tvim\n%3\tfish\n%4\t-bash\n")
+            .with(keys("%1", posix), Outcome::Output(String::new()))
+            .with(keys("%3", fish), Outcome::Output(String::new()))
+            .with(keys("%4", posix), Outcome::Output(String::new()));
+        broadcast_environment(&sh, "s", &["SSH_AUTH_SOCK".into(), "DISPLAY".into()])?;
+        assert!(sh.unused().is_empty(), "{:?}", sh.unused());
+        Ok(())
     }
 
     #[test]
-    fn list_sessions_sorts() -> Result<()> {
-        struct RShell;
-        impl crate::shell::Shell for RShell {
-            fn run(&self, _p: &str, _a: &[&str]) -> Result<String> {
-                Ok("b\na\n".into())
-            }
-            fn run_status(&self, _p: &str, _a: &[&str]) -> Result<bool> {
-                Ok(true)
-            }
-            fn run_tty(&self, _p: &str, _a: &[&str]) -> Result<()> {
-                Ok(())
-            }
-            fn env_var(&self, _k: &str) -> Option<String> {
-                None
-            }
-        }
-        let v = list_sessions(&RShell)?;
-        assert_eq!(v, vec!["a", "b"]);
+    fn socket_parse_name_or_path() {
+        assert_eq!(Socket::parse("work"), Socket::Name("work".into()));
+        assert_eq!(Socket::parse("/tmp/s"), Socket::Path("/tmp/s".into()));
+    }
+
+    #[test]
+    fn socket_path_resolves_the_server_socket() -> Result<()> {
+        let outside = ReplayShell::default()
+            .env("TMUX_TMPDIR", "/run/t")
+            .output("id", &["-u"], "1000\n")
+            .output("id", &["-u"], "1000\n");
+        let inside = ReplayShell::default().env("TMUX", "/run/t/tmux-1000/work,42,0");
+        assert_eq!(
+            Socket::Default.path(&outside)?,
+            PathBuf::from("/run/t/tmux-1000/default")
+        );
+        assert_eq!(
+            Socket::Name("work".into()).path(&outside)?,
+            PathBuf::from("/run/t/tmux-1000/work")
+        );
+        assert_eq!(
+            Socket::Default.path(&inside)?,
+            PathBuf::from("/run/t/tmux-1000/work")
+        );
+        assert_eq!(
+            Socket::Path("/p".into()).path(&inside)?,
+            PathBuf::from("/p")
+        );
         Ok(())
     }
 
     #[test]
-    fn has_session_true_false() -> Result<()> {
-        let sh_true = MockShell::default().status("tmux", &["has-session", "-t", "x"], true);
-        assert!(has_session(&sh_true, "x")?);
-        let sh_false = MockShell::default().status("tmux", &["has-session", "-t", "x"], false);
-        assert!(!has_session(&sh_false, "x")?);
+    fn server_sockets_lists_sockets_default_first() -> Result<()> {
+        let base = std::env::temp_dir().join(format!("nitro-sockets-{}", std::process::id()));
+        let dir = base.join("tmux-1000");
+        std::fs::create_dir_all(&dir)?;
+        let _a = std::os::unix::net::UnixListener::bind(dir.join("work"))?;
+        let _b = std::os::unix::net::UnixListener::bind(dir.join("default"))?;
+        std::fs::write(dir.join("not-a-socket"), "")?;
+        let in_dir = |tmpdir: &std::path::Path| {
+            ReplayShell::default()
+                .env("TMUX_TMPDIR", &tmpdir.to_string_lossy())
+                .output("id", &["-u"], "1000\n")
+        };
+        let names = server_sockets(&in_dir(&base));
+        let missing = server_sockets(&in_dir(&base.join("nope")));
+        std::fs::remove_dir_all(&base)?;
+        assert_eq!(names?, vec!["default", "work"]);
+        assert!(missing?.is_empty());
         Ok(())
     }
 
     #[test]
     fn attach_or_switch_respects_tmux_env() -> Result<()> {
-        let sh_in = MockShell::default().with_env("TMUX", "1");
+        let sh_in = ReplayShell::default().env("TMUX", "1").output(
+            "tmux",
+            &["switch-client", "-t", "=name"],
+            "",
+        );
         attach_or_switch(&sh_in, "name")?;
-        let sh_out = MockShell::default();
+        let sh_out = ReplayShell::default().tty("tmux", &["attach", "-t", "=name"]);
         attach_or_switch(&sh_out, "name")?;
+        assert!(sh_in.unused().is_empty() && sh_out.unused().is_empty());
         Ok(())
     }
 }
@@ -0,0 +1,506 @@
+use crate::error::{self, NitroError};
+use crate::shell::Shell;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// One `Shell` call, as stored in a transcript.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "call", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Call {
+    Run { program: String, args: Vec<String> },
+    RunStatus { program: String, args: Vec<String> },
+    RunTty { program: String, args: Vec<String> },
+    EnvVar { key: String },
+    Confirm { prompt: String },
+    HasTerminal,
+}
+
+impl Call {
+    fn command(kind: fn(String, Vec<String>) -> Call, program: &str, args: &[&str]) -> Call {
+        kind(
+            program.to_string(),
+            args.iter().map(|s| s.to_string()).collect(),
+        )
+    }
+
+    fn run(program: String, args: Vec<String>) -> Call {
+        Call::Run { program, args }
+    }
+
+    fn run_status(program: String, args: Vec<String>) -> Call {
+        Call::RunStatus { program, args }
+    }
+
+    fn run_tty(program: String, args: Vec<String>) -> Call {
+        Call::RunTty { program, args }
+    }
+}
+
+/// What a call returned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Outcome {
+    /// stdout of `run`
+    Output(String),
+    /// Result of `run_status`
+    Status(bool),
+    /// `run_tty` succeeded
+    Done,
+    /// Result of `env_var`
+    Value(Option<String>),
+    /// Result of `confirm` or `has_terminal`
+    Answer(bool),
+    Error {
+        /// The full error chain as displayed
+        message: String,
+        /// The typed error, when there was one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nitro: Option<NitroError>,
+    },
+}
+
+impl Outcome {
+    fn from_error(err: &anyhow::Error) -> Outcome {
+        Outcome::Error {
+            message: format!("{err:#}"),
+            nitro: error::find(err).cloned(),
+        }
+    }
+
+    fn into_error(self) -> anyhow::Error {
+        match self {
+            Outcome::Error {
+                nitro: Some(nitro), ..
+            } => nitro.into(),
+            Outcome::Error { message, .. } => anyhow::anyhow!(message),
+            other => anyhow::anyhow!("unexpected recorded outcome {:?}", other),
+        }
+    }
+}
+
+/// One line of a transcript file (JSON Lines).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    #[serde(flatten)]
+    pub call: Call,
+    pub outcome: Outcome,
+}
+
+/// Wraps a shell and appends every call and its outcome to a transcript,
+/// as set up by `NITRO_RECORD=path`. Transcripts contain command output
+/// and the environment variables nitro looked at.
+pub struct RecordingShell<S: Shell> {
+    inner: S,
+    out: RefCell<File>,
+}
+
+impl<S: Shell> RecordingShell<S> {
+    /// Record into `path`, replacing an existing file.
+    pub fn create(inner: S, path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create transcript {}", path.display()))?;
+        Ok(RecordingShell {
+            inner,
+            out: RefCell::new(file),
+        })
+    }
+
+    fn record(&self, call: Call, outcome: Outcome) {
+        // A transcript is a debugging aid; failing to write it must not
+        // change what nitro does.
+        if let Ok(mut line) = serde_json::to_string(&Record { call, outcome }) {
+            line.push('\n');
+            let _ = self.out.borrow_mut().write_all(line.as_bytes());
+        }
+    }
+
+    fn record_result<T>(&self, call: Call, res: &Result<T>, ok: impl Fn(&T) -> Outcome) {
+        let outcome = match res {
+            Ok(v) => ok(v),
+            Err(e) => Outcome::from_error(e),
+        };
+        self.record(call, outcome);
+    }
+}
+
+impl<S: Shell> Shell for RecordingShell<S> {
+    fn run(&self, program: &str, args: &[&str]) -> Result<String> {
+        let res = self.inner.run(program, args);
+        let call = Call::command(Call::run, program, args);
+        self.record_result(call, &res, |out| Outcome::Output(out.clone()));
+        res
+    }
+    fn run_status(&self, program: &str, args: &[&str]) -> Result<bool> {
+        let res = self.inner.run_status(program, args);
+        let call = Call::command(Call::run_status, program, args);
+        self.record_result(call, &res, |ok| Outcome::Status(*ok));
+        res
+    }
+    fn run_tty(&self, program: &str, args: &[&str]) -> Result<()> {
+        let res = self.inner.run_tty(program, args);
+        let call = Call::command(Call::run_tty, program, args);
+        self.record_result(call, &res, |_| Outcome::Done);
+        res
+    }
+    /// Recorded as a `run` call (without the input), so replays answer it
+    /// from the transcript.
+    fn run_picker(&self, program: &str, args: &[&str], input: &str) -> Result<String> {
+        let res = self.inner.run_picker(program, args, input);
+        let call = Call::command(Call::run, program, args);
+        self.record_result(call, &res, |out| Outcome::Output(out.clone()));
+        res
+    }
+    /// Recorded as a `run` call with no output, so replays answer it from
+    /// the transcript.
+    fn run_interactive(&self, program: &str, args: &[&str]) -> Result<()> {
+        let res = self.inner.run_interactive(program, args);
+        let call = Call::command(Call::run, program, args);
+        self.record_result(call, &res, |_| Outcome::Output(String::new()));
+        res
+    }
+    fn env_var(&self, key: &str) -> Option<String> {
+        let value = self.inner.env_var(key);
+        let call = Call::EnvVar {
+            key: key.to_string(),
+        };
+        self.record(call, Outcome::Value(value.clone()));
+        value
+    }
+    fn confirm(&self, prompt: &str) -> bool {
+        let answer = self.inner.confirm(prompt);
+        let call = Call::Confirm {
+            prompt: prompt.to_string(),
+        };
+        self.record(call, Outcome::Answer(answer));
+        answer
+    }
+    fn has_terminal(&self) -> bool {
+        let answer = self.inner.has_terminal();
+        self.record(Call::HasTerminal, Outcome::Answer(answer));
+        answer
+    }
+}
+
+/// Answers calls from a transcript instead of running anything, as set up
+/// by `NITRO_REPLAY=path`; also usable as a scripted fake in tests.
+///
+/// Each recorded call answers one matching call, in recorded order, so
+/// repeated calls can return different results. Environment variables keep
+/// their last answer once used up; unknown variables are unset. A command
+/// with no recorded answer left is an error.
+#[derive(Default)]
+pub struct ReplayShell {
+    records: RefCell<Vec<(Record, bool)>>,
+    /// Every command asked, in order
+    log: RefCell<Vec<Call>>,
+    /// Commands without an answer succeed instead of failing
+    lenient: bool,
+}
+
+impl ReplayShell {
+    pub fn new(records: Vec<Record>) -> Self {
+        ReplayShell {
+            records: RefCell::new(records.into_iter().map(|r| (r, false)).collect()),
+            ..ReplayShell::default()
+        }
+    }
+
+    /// Parse a transcript; blank lines are ignored.
+    pub fn parse(text: &str) -> Result<Self> {
+        let records = text
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty())
+            .map(|(i, l)| {
+                serde_json::from_str(l).with_context(|| format!("transcript line {}", i + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(ReplayShell::new(records))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read transcript {}", path.display()))?;
+        ReplayShell::parse(&text)
+    }
+
+    /// Take the next unused answer to `call`.
+    fn answer(&self, call: &Call) -> Option<Outcome> {
+        let mut records = self.records.borrow_mut();
+        let (rec, used) = records
+            .iter_mut()
+            .find(|(r, used)| !*used && r.call == *call)?;
+        *used = true;
+        Some(rec.outcome.clone())
+    }
+
+    fn answer_command(&self, call: Call) -> Result<Outcome> {
+        self.log.borrow_mut().push(call.clone());
+        match self.answer(&call) {
+            Some(Outcome::Error { message, nitro }) => {
+                Err(Outcome::Error { message, nitro }.into_error())
+            }
+            Some(outcome) => Ok(outcome),
+            None if self.lenient => Ok(match call {
+                Call::RunStatus { .. } => Outcome::Status(true),
+                Call::RunTty { .. } => Outcome::Done,
+                _ => Outcome::Output(String::new()),
+            }),
+            None => anyhow::bail!("transcript has no answer for {:?}", call),
+        }
+    }
+}
+
+/// Scripting, for tests.
+#[cfg(test)]
+impl ReplayShell {
+    /// Script a call's outcome.
+    pub fn with(self, call: Call, outcome: Outcome) -> Self {
+        self.records
+            .borrow_mut()
+            .push((Record { call, outcome }, false));
+        self
+    }
+
+    /// Script `run` returning `output`.
+    pub fn output(self, program: &str, args: &[&str], output: &str) -> Self {
+        let call = Call::command(Call::run, program, args);
+        self.with(call, Outcome::Output(output.to_string()))
+    }
+
+    /// Script `run_status` returning `ok`.
+    pub fn status(self, program: &str, args: &[&str], ok: bool) -> Self {
+        let call = Call::command(Call::run_status, program, args);
+        self.with(call, Outcome::Status(ok))
+    }
+
+    /// Script `run_tty` succeeding.
+    pub fn tty(self, program: &str, args: &[&str]) -> Self {
+        let call = Call::command(Call::run_tty, program, args);
+        self.with(call, Outcome::Done)
+    }
+
+    /// Script `run` failing with `err`.
+    pub fn fail(self, program: &str, args: &[&str], err: NitroError) -> Self {
+        let call = Call::command(Call::run, program, args);
+        let outcome = Outcome::Error {
+            message: err.to_string(),
+            nitro: Some(err),
+        };
+        self.with(call, outcome)
+    }
+
+    pub fn env(self, key: &str, value: &str) -> Self {
+        let call = Call::EnvVar {
+            key: key.to_string(),
+        };
+        self.with(call, Outcome::Value(Some(value.to_string())))
+    }
+
+    /// Script `confirm` answering yes to `prompt`.
+    pub fn confirm_yes(self, prompt: &str) -> Self {
+        let call = Call::Confirm {
+            prompt: prompt.to_string(),
+        };
+        self.with(call, Outcome::Answer(true))
+    }
+
+    /// Script `has_terminal` saying no.
+    pub fn no_terminal(self) -> Self {
+        self.with(Call::HasTerminal, Outcome::Answer(false))
+    }
+
+    /// Answer commands that were not scripted as if they succeeded: no
+    /// output, and `run_status` says yes. Check what ran with `calls`.
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    /// Every command asked so far, in order, scripted or not.
+    pub fn calls(&self) -> Vec<Call> {
+        self.log.borrow().clone()
+    }
+
+    /// Recorded commands nobody asked for yet.
+    pub fn unused(&self) -> Vec<Call> {
+        self.records
+            .borrow()
+            .iter()
+            .filter(|(r, used)| !used && !matches!(r.call, Call::EnvVar { .. }))
+            .map(|(r, _)| r.call.clone())
+            .collect()
+    }
+}
+
+impl Shell for ReplayShell {
+    fn run(&self, program: &str, args: &[&str]) -> Result<String> {
+        match self.answer_command(Call::command(Call::run, program, args))? {
+            Outcome::Output(out) => Ok(out),
+            other => Err(other.into_error()),
+        }
+    }
+    fn run_status(&self, program: &str, args: &[&str]) -> Result<bool> {
+        match self.answer_command(Call::command(Call::run_status, program, args))? {
+            Outcome::Status(ok) => Ok(ok),
+            other => Err(other.into_error()),
+        }
+    }
+    fn run_tty(&self, program: &str, args: &[&str]) -> Result<()> {
+        match self.answer_command(Call::command(Call::run_tty, program, args))? {
+            Outcome::Done => Ok(()),
+            other => Err(other.into_error()),
+        }
+    }
+    fn env_var(&self, key: &str) -> Option<String> {
+        let call = Call::EnvVar {
+            key: key.to_string(),
+        };
+        let outcome = self.answer(&call).or_else(|| {
+            let records = self.records.borrow();
+            let last = records.iter().rev().find(|(r, _)| r.call == call);
+            last.map(|(r, _)| r.outcome.clone())
+        });
+        match outcome {
+            Some(Outcome::Value(v)) => v,
+            _ => None,
+        }
+    }
+    fn confirm(&self, prompt: &str) -> bool {
+        let call = Call::Confirm {
+            prompt: prompt.to_string(),
+        };
+        matches!(self.answer(&call), Some(Outcome::Answer(true)))
+    }
+    /// Transcripts from before `has_terminal` was recorded had one.
+    fn has_terminal(&self) -> bool {
+        !matches!(
+            self.answer(&Call::HasTerminal),
+            Some(Outcome::Answer(false))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The answers to record, scripted.
+    fn scripted() -> ReplayShell {
+        let kill = ["kill-server"];
+        let attach = Call::command(Call::run_tty, "tmux", &["attach"]);
+        ReplayShell::default()
+            .output("tmux", &["list-sessions"], "api\nweb\n")
+            .fail(
+                "tmux",
+                &kill,
+                NitroError::command_failed("tmux", &kill, "no server running"),
+            )
+            .status("tmux", &["has-session", "-t", "x"], false)
+            .with(
+                attach,
+                Outcome::from_error(&NitroError::NotATerminal.into()),
+            )
+            .env("TMUX", "/tmp/tmux-1000/default,1,0")
+            .confirm_yes("Create it?")
+            .no_terminal()
+    }
+
+    fn exercise<S: Shell>(sh: &S) -> Vec<String> {
+        vec![
+            format!("{:?}", sh.run("tmux", &["list-sessions"]).ok()),
+            format!(
+                "{:?}",
+                error::find(&sh.run("tmux", &["kill-server"]).unwrap_err())
+            ),
+            format!(
+                "{:?}",
+                sh.run_status("tmux", &["has-session", "-t", "x"]).ok()
+            ),
+            format!(
+                "{:?}",
+                error::find(&sh.run_tty("tmux", &["attach"]).unwrap_err())
+            ),
+            format!("{:?}", sh.env_var("TMUX")),
+            format!("{:?}", sh.env_var("HOME")),
+            format!("{:?}", sh.confirm("Create it?")),
+            format!("{:?}", sh.has_terminal()),
+        ]
+    }
+
+    #[test]
+    fn record_then_replay_gives_the_same_answers() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("nitro-transcript-{}", std::process::id()));
+        let live = {
+            let rec = RecordingShell::create(scripted(), &path)?;
+            exercise(&rec)
+        };
+        let replay = ReplayShell::load(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(exercise(&replay), live);
+        assert!(replay.unused().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn replay_without_terminal_record_has_one() {
+        assert!(ReplayShell::default().has_terminal());
+    }
+
+    #[test]
+    fn transcript_lines_are_readable_json() -> Result<()> {
+        let line = r#"{"call":"run","program":"tmux","args":["list-sessions"],"outcome":{"output":"api\n"}}"#;
+        let sh = ReplayShell::parse(&format!("{line}\n\n"))?;
+        assert_eq!(sh.run("tmux", &["list-sessions"])?, "api\n");
+        assert!(ReplayShell::parse("{").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn scripted_answers_are_used_in_order() {
+        let sh = ReplayShell::default()
+            .status("tmux", &["has-session", "-t", "api"], false)
+            .status("tmux", &["has-session", "-t", "api"], true)
+            .fail(
+                "zoxide",
+                &["query", "-ls"],
+                NitroError::MissingProgram("zoxide".into()),
+            )
+            .env("TMUX", "1");
+        assert!(
+            !sh.run_status("tmux", &["has-session", "-t", "api"])
+                .unwrap()
+        );
+        assert!(
+            sh.run_status("tmux", &["has-session", "-t", "api"])
+                .unwrap()
+        );
+        assert!(
+            sh.run_status("tmux", &["has-session", "-t", "api"])
+                .is_err()
+        );
+        let err = sh.run("zoxide", &["query", "-ls"]).unwrap_err();
+        assert_eq!(
+            error::find(&err),
+            Some(&NitroError::MissingProgram("zoxide".into()))
+        );
+        // variables keep answering once used
+        assert_eq!(sh.env_var("TMUX").as_deref(), Some("1"));
+        assert_eq!(sh.env_var("TMUX").as_deref(), Some("1"));
+        assert_eq!(sh.env_var("HOME"), None);
+        assert!(sh.unused().is_empty());
+    }
+
+    #[test]
+    fn drives_nitro_code() -> Result<()> {
+        let sh = ReplayShell::default().output("tmux", &["list-sessions", "-F", "#S"], "b\na\n");
+        assert_eq!(crate::tmux::list_sessions(&sh)?, ["a", "b"]);
+        Ok(())
+    }
+}
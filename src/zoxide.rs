@@ -1,22 +1,148 @@
+use crate::error;
 use anyhow::Result;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
+/// Seconds a directory check is reused by `nitro list`; `nitro fzf` reloads
+/// list again and again within a few seconds.
+const DIR_CACHE_TTL: u64 = 60;
+
+#[non_exhaustive]
 pub struct ZItem {
     pub path: PathBuf,
+    /// Frecency score, when zoxide reported one
+    pub score: Option<f64>,
+}
+
+/// Parse one `zoxide query -ls` line ("  12.5 /path"); a bare path is
+/// accepted too and has no score.
+fn parse_line(line: &str) -> Option<ZItem> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    if let Some((score, path)) = line.split_once(' ')
+        && let Ok(score) = score.parse::<f64>()
+    {
+        return Some(ZItem {
+            path: PathBuf::from(path.trim_start()),
+            score: Some(score),
+        });
+    }
+    Some(ZItem {
+        path: PathBuf::from(line),
+        score: None,
+    })
 }
 
+/// All zoxide entries in ranking order. An empty database yields an empty
+/// list; a missing binary or unreadable database is an error.
 pub fn list_all<S: crate::shell::Shell>(sh: &S) -> Result<Vec<ZItem>> {
-    let out = sh.run("zoxide", &["query", "-l"]).unwrap_or_default();
-    let mut items = Vec::new();
-    for line in out.lines() {
-        let p = line.trim();
-        if p.is_empty() {
-            continue;
+    let out = sh.run("zoxide", &["query", "-ls"])?;
+    Ok(out.lines().filter_map(parse_line).collect())
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct DirCheck {
+    time: u64,
+    is_dir: bool,
+}
+
+/// Directory checks kept in a file between runs, so listing again within
+/// [`DIR_CACHE_TTL`] seconds does not stat every zoxide path again.
+pub struct DirCache {
+    path: Option<PathBuf>,
+    now: u64,
+    checks: RefCell<HashMap<String, DirCheck>>,
+    changed: Cell<bool>,
+}
+
+impl DirCache {
+    /// The cache stored at `path` (a missing or unreadable file is an empty
+    /// cache); `None` checks every time and stores nothing.
+    pub fn load(path: Option<&Path>, now: u64) -> DirCache {
+        let checks = path
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        DirCache {
+            path: path.map(Path::to_path_buf),
+            now,
+            checks: RefCell::new(checks),
+            changed: Cell::new(false),
+        }
+    }
+
+    /// Whether `path` is a directory, checked now unless a check younger
+    /// than the TTL is cached.
+    pub fn is_dir(&self, path: &Path) -> bool {
+        let key = path.to_string_lossy().to_string();
+        if let Some(hit) = self.checks.borrow().get(&key)
+            && self.now.saturating_sub(hit.time) < DIR_CACHE_TTL
+        {
+            return hit.is_dir;
+        }
+        let is_dir = path.is_dir();
+        let check = DirCheck {
+            time: self.now,
+            is_dir,
+        };
+        self.checks.borrow_mut().insert(key, check);
+        self.changed.set(true);
+        is_dir
+    }
+
+    /// Write new checks back, dropping expired ones. The cache only saves
+    /// time, so failing to write it is not an error.
+    pub fn save(&self) {
+        let Some(path) = self.path.as_deref().filter(|_| self.changed.get()) else {
+            return;
+        };
+        let mut checks = self.checks.borrow_mut();
+        checks.retain(|_, c| self.now.saturating_sub(c.time) < DIR_CACHE_TTL);
+        if let Ok(text) = serde_json::to_string(&*checks) {
+            let _ = crate::config::write_cache(path, &text);
+        }
+    }
+}
+
+pub fn remove<S: crate::shell::Shell>(sh: &S, path: &Path) -> Result<()> {
+    let _ = sh.run("zoxide", &["remove", &path.to_string_lossy()])?;
+    Ok(())
+}
+
+/// Whether a path is gone for good. Only "not found" counts: a path that
+/// cannot be checked right now (no permission, an unmounted drive or
+/// share) is an error.
+fn is_missing(path: &Path) -> Result<bool> {
+    match std::fs::metadata(path) {
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(true),
+        Err(e) => Err(anyhow::Error::new(e).context(format!("cannot check {}", path.display()))),
+    }
+}
+
+/// Remove zoxide entries whose directory no longer exists. Prints each
+/// pruned path; with `dry_run` nothing is removed. Paths that cannot be
+/// checked are kept, with a warning.
+pub fn run_prune<S: crate::shell::Shell>(sh: &S, dry_run: bool) -> Result<()> {
+    for it in list_all(sh)? {
+        match is_missing(&it.path) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                error::warn(&e);
+                continue;
+            }
+        }
+        if !dry_run {
+            remove(sh, &it.path)?;
         }
-        let path = PathBuf::from(p);
-        items.push(ZItem { path });
+        println!("{}", it.path.display());
     }
-    Ok(items)
+    Ok(())
 }
 
 pub fn best_match_dir<S: crate::shell::Shell>(sh: &S, query: &str) -> Option<PathBuf> {
@@ -37,65 +163,101 @@ pub fn best_match_dir<S: crate::shell::Shell>(sh: &S, query: &str) -> Option<Pat
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::NitroError;
+    use crate::transcript::ReplayShell;
     use anyhow::Result;
 
+    const QUERY: [&str; 2] = ["query", "-ls"];
+
     #[test]
     fn list_all_parses_and_ignores_empty() -> Result<()> {
-        struct RShell;
-        impl crate::shell::Shell for RShell {
-            fn run(&self, _p: &str, _a: &[&str]) -> Result<String> {
-                Ok("/a\n\n /b \n".into())
-            }
-            fn run_status(&self, _p: &str, _a: &[&str]) -> Result<bool> {
-                Ok(true)
-            }
-            fn run_tty(&self, _p: &str, _a: &[&str]) -> Result<()> {
-                Ok(())
-            }
-            fn env_var(&self, _k: &str) -> Option<String> {
-                None
-            }
-        }
-        let v = list_all(&RShell)?;
+        let sh = ReplayShell::default().output("zoxide", &QUERY, "/a\n\n /b \n");
+        let v = list_all(&sh)?;
         assert_eq!(v.len(), 2);
         assert_eq!(v[0].path, PathBuf::from("/a"));
         assert_eq!(v[1].path, PathBuf::from("/b"));
+        assert_eq!(v[1].score, None);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_line_with_scores() {
+        let it = parse_line("  12.5 /src/my project").unwrap();
+        assert_eq!(it.score, Some(12.5));
+        assert_eq!(it.path, PathBuf::from("/src/my project"));
+        let it = parse_line(" 100 /a").unwrap();
+        assert_eq!(it.score, Some(100.0));
+        assert!(parse_line("   ").is_none());
+    }
+
+    #[test]
+    fn list_all_propagates_errors() {
+        let err = NitroError::command_failed("zoxide", &QUERY, "database is corrupted");
+        let sh = ReplayShell::default().fail("zoxide", &QUERY, err);
+        let err = list_all(&sh).err().unwrap();
+        assert!(err.to_string().contains("database is corrupted"));
+    }
+
+    #[test]
+    fn dir_cache_reuses_checks_across_runs() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("nitro-dircache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let project = dir.join("project");
+        std::fs::create_dir_all(&project)?;
+        let file = dir.join("dirs.json");
+
+        let cache = DirCache::load(Some(&file), 1000);
+        assert!(cache.is_dir(&project));
+        cache.save();
+        std::fs::remove_dir(&project)?;
+        // Within the TTL the next run trusts the saved check
+        assert!(DirCache::load(Some(&file), 1000 + DIR_CACHE_TTL - 1).is_dir(&project));
+        // After it, the path is checked again
+        assert!(!DirCache::load(Some(&file), 1000 + DIR_CACHE_TTL).is_dir(&project));
+        assert!(!DirCache::load(None, 1000).is_dir(&project));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn prune_removes_only_missing_dirs() -> Result<()> {
+        let listing = format!(
+            "{}\n/nitro/definitely/gone\n",
+            std::env::temp_dir().display()
+        );
+        let sh = ReplayShell::default()
+            .output("zoxide", &QUERY, &listing)
+            .output("zoxide", &QUERY, &listing)
+            .output("zoxide", &["remove", "/nitro/definitely/gone"], "");
+        run_prune(&sh, true)?;
+        assert_eq!(sh.unused().len(), 2);
+        run_prune(&sh, false)?;
+        assert!(sh.unused().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn prune_keeps_paths_it_cannot_check() -> Result<()> {
+        // A symlink loop fails with ELOOP rather than "not found"
+        let dir = std::env::temp_dir().join(format!("nitro-prune-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir)?;
+        let looped = dir.join("loop");
+        std::os::unix::fs::symlink(&looped, &looped)?;
+        let listing = format!("{}\n", looped.display());
+        let sh = ReplayShell::default().output("zoxide", &QUERY, &listing);
+        assert!(is_missing(&looped).is_err());
+        run_prune(&sh, false)?;
+        assert!(sh.unused().is_empty()); // no `zoxide remove` was scripted
+        std::fs::remove_dir_all(&dir)?;
         Ok(())
     }
 
     #[test]
     fn best_match_ok_and_err() {
-        struct OkShell;
-        impl crate::shell::Shell for OkShell {
-            fn run(&self, _p: &str, _a: &[&str]) -> Result<String> {
-                Ok("/match\n".into())
-            }
-            fn run_status(&self, _p: &str, _a: &[&str]) -> Result<bool> {
-                Ok(true)
-            }
-            fn run_tty(&self, _p: &str, _a: &[&str]) -> Result<()> {
-                Ok(())
-            }
-            fn env_var(&self, _k: &str) -> Option<String> {
-                None
-            }
-        }
-        struct ErrShell;
-        impl crate::shell::Shell for ErrShell {
-            fn run(&self, _p: &str, _a: &[&str]) -> Result<String> {
-                anyhow::bail!("boom")
-            }
-            fn run_status(&self, _p: &str, _a: &[&str]) -> Result<bool> {
-                Ok(true)
-            }
-            fn run_tty(&self, _p: &str, _a: &[&str]) -> Result<()> {
-                Ok(())
-            }
-            fn env_var(&self, _k: &str) -> Option<String> {
-                None
-            }
-        }
-        assert_eq!(best_match_dir(&OkShell, "x"), Some(PathBuf::from("/match")));
-        assert_eq!(best_match_dir(&ErrShell, "x"), None);
+        let sh = ReplayShell::default().output("zoxide", &["query", "x"], "/match\n");
+        assert_eq!(best_match_dir(&sh, "x"), Some(PathBuf::from("/match")));
+        // used up: the next query fails
+        assert_eq!(best_match_dir(&sh, "x"), None);
     }
 }